@@ -1,7 +1,7 @@
 use crate::event::instance::StatusPayload;
 use std::collections::HashMap;
 use std::fs::{read_dir};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::AtomicI64;
 use async_recursion::async_recursion;
@@ -9,20 +9,23 @@ use rand::distributions::Alphanumeric;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Manager, State};
-use crate::constant::{ASSET_ROOT, LIB_PATH, NO_SIZE_DEFAULT_SIZE};
+use crate::constant::{ASSET_ROOT, CLIENT_ID, LIB_PATH, NATIVES_ROOT, NO_SIZE_DEFAULT_SIZE};
 use crate::utils::config::{Storage, SafeNoLauncherConfig, NoLauncherConfig, Save, SavePath, Load};
 use crate::utils::minecraft::instance::{get_launch_data, InstanceLock, GameFile, InstanceConfig, LaunchData, SafeInstanceStatus, Status, FileType};
-use crate::utils::minecraft::metadata::{decode_hex};
+use crate::utils::minecraft::metadata::{decode_hex, Environment};
 use crate::utils::minecraft::metadata::SHAType::SHA256;
-use crate::utils::result::CommandResult;
+use crate::utils::result::{CommandResult, IntoTAResult};
 use anyhow::{anyhow, Result};
-use log::{error, info};
+use log::{debug, error, info, warn};
 use tauri_plugin_shell::process::{CommandChild, CommandEvent};
 use tauri_plugin_shell::ShellExt;
 use tauri::async_runtime::Receiver;
-use tokio::sync::{RwLock, Semaphore};
+use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
-use crate::utils::minecraft::auth::{Account, AccountList};
+use crate::utils::minecraft::auth::{MinecraftAuthorizationFlow, SafeAccountList};
+use crate::utils::minecraft::yggdrasil::authlib_injector_args;
+use crate::utils::minecraft::arguments::{apply_placeholders, resolve, Features, LaunchPlaceholders};
+use crate::utils::minecraft::natives::{clean_natives, extract_natives};
 
 const MINECRAFT_UID:&str = "net.minecraft";
 const FABRIC_UID:&str = "net.fabricmc.fabric-loader";
@@ -252,7 +255,8 @@ pub async fn create_instance(
         id:uuid.clone(),
         name:request.name.clone(),
         dep,
-        top:uid
+        top:uid,
+        account:None,
     };
     
     let instance_path = SavePath::from_data(&app,vec![&uuid]).unwrap();
@@ -272,7 +276,8 @@ pub async fn create_instance(
 #[derive(Serialize,Debug)]
 pub struct InstanceInfo{
     pub id:String,
-    pub name:String
+    pub name:String,
+    pub account:Option<String>,
 }
 
 #[tauri::command]
@@ -295,11 +300,51 @@ pub async  fn list_instance(
         }
     }
     
-    let vec = vec.iter().map(|x| InstanceInfo{ id: x.id.to_string(), name: x.name.to_string()}).collect();
+    let vec = vec.iter().map(|x| InstanceInfo{
+        id: x.id.to_string(),
+        name: x.name.to_string(),
+        account: x.account.clone(),
+    }).collect();
     
     Ok(vec)
 }
 
+/// Pin the account to launch the instance with, or use the active user when `account` is null.
+#[tauri::command]
+pub async fn set_instance_account(
+    id:String,
+    account:Option<String>,
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+) -> CommandResult<()>{
+    if let Some(account) = &account {
+        crate::ensure!(accounts.read().await.get(account).is_some(), "no user found: {}", account);
+    }
+
+    let path = SavePath::from_data(&app,vec![&id,"instance.json"])?;
+    let mut instance_config = *InstanceConfig::load(&path)?;
+    instance_config.account = account;
+    instance_config.save(&path)?;
+    Ok(())
+}
+
+/// The account pinned by the instance, or the active user when there is none,
+/// or the pinned one was logged out.
+async fn launch_user(id:&str, app:&AppHandle, config:&SafeNoLauncherConfig) -> Option<String>{
+    let pinned = SavePath::from_data(app,vec![id,"instance.json"]).ok()
+        .and_then(|path| InstanceConfig::load(&path).ok())
+        .and_then(|x| x.account);
+
+    if let Some(pinned) = pinned {
+        if app.state::<SafeAccountList>().read().await.get(&pinned).is_some() {
+            return Some(pinned);
+        }
+        warn!("The account {pinned} of instance {id} is not found, use the active user instead");
+    }
+
+    config.read().await.activate_user_uuid.clone()
+}
+
 async fn prepare(
     id:&str,
     app: &AppHandle,
@@ -384,18 +429,41 @@ async fn running(
     app:&AppHandle,
     map:&SafeInstanceStatus,
     launch:&LaunchData,
-    userid:Option<String>
+    userid:Option<String>,
+    natives_dir:&Path,
 ) -> Result<Receiver<CommandEvent>>{
     
 
-    let userlist = app.state::<RwLock<AccountList>>();
-    let user = userlist.write().await.find(&userid.unwrap_or("".to_string())).cloned().ok_or(anyhow!("no user found"))?;
+    let userlist = app.state::<SafeAccountList>();
+    let userid = userid.unwrap_or_default();
+
+    // the tokens are refreshed on a clone, so the other commands are not blocked by the network
+    let mut user = userlist.read().await.get(&userid).cloned().ok_or(anyhow!("no user found"))?;
+    let auth = user.launch_auth(&MinecraftAuthorizationFlow::new(CLIENT_ID)).await?;
+
+    {
+        let mut userlist = userlist.write().await;
+        if let Some(account) = userlist.find(&userid) {
+            account.update_tokens(user);
+            account.mark_used();
+        }
+
+        // the new minecraft token should be kept for next launch
+        if let Err(e) = userlist.save_by_app(app) {
+            warn!("{:?}", e);
+        }
+    }
+
+    info!("Running game with user: {}",auth.name);
 
-    info!("Running game: {:?}",user);
+    let agent_args = match &auth.yggdrasil_api {
+        Some(api_root) => authlib_injector_args(app, api_root).await?,
+        None => vec![],
+    };
     
     let classpath = game_files.iter()
-        // we don't need asset and installer in classpath
-        .filter(|x| x.file_type != FileType::Asset)
+        // we don't need asset and natives in classpath
+        .filter(|x| x.file_type != FileType::Asset && !matches!(x.file_type, FileType::Native(_)))
         .map(|x|x.get_fullpath().to_str().unwrap().to_string())
         .collect::<Vec<String>>()
         .join(":");// windows use ";"
@@ -411,6 +479,11 @@ async fn running(
         Some(temp) => {temp.get_fullpath()}
     };
     
+    // the natives of lwjgl 2 are loaded from java.library.path, so they should be extracted.
+    let files = game_files.clone();
+    let dir = natives_dir.to_path_buf();
+    tauri::async_runtime::spawn_blocking(move || extract_natives(&files, &dir)).await??;
+
     let shell = app.shell();
     
     let mut command = shell.command("java");
@@ -425,41 +498,62 @@ async fn running(
     let client_args = &format!("-Dforgewrapper.minecraft={}",client.to_str().unwrap());
 
     
-    let jvm_args = vec![
-        lib_path_args,
-        installer_args,
-        client_args,
-        "-cp",
-        &classpath,
-        &launch.main_class, // main class must be last one
-    ];
-    
-    println!("{:?}",jvm_args);
-
-    let launch_arg_mapping = HashMap::from([
-        ("${assets_root}",assets_folder.to_str().unwrap()),
-        ("${assets_index_name}", &launch.asset_index.id),
-        ("${game_directory}",game_dir.to_str().unwrap()),
-        ("${auth_access_token}","wtf"),
-        ("${auth_uuid}", "280796c9-bf94-4abd-98bb-f0b89be44d76"),
-        ("${user_type}","msa"),
-        ("${version_name}","test"),
+    let env = Environment{
+        features: Features{
+            is_demo_user: auth.demo,
+            ..Default::default()
+        },
+        ..Environment::host()
+    };
+
+    // the versions before 1.13 don't have jvm arguments
+    let version_jvm_args = match launch.jvm_args.is_empty() {
+        true => vec![
+            "-Djava.library.path=${natives_directory}".to_string(),
+            "-cp".to_string(),
+            "${classpath}".to_string(),
+        ],
+        false => resolve(&launch.jvm_args, &env),
+    };
+    let game_args = resolve(&launch.game_args, &env);
+
+    let launch_arg_mapping = LaunchPlaceholders {
+        auth: &auth,
+        client_id: CLIENT_ID,
+        version_name: &launch.version_name,
+        version_type: launch.version_type.as_deref().unwrap_or("release"),
+        classpath: &classpath,
+        natives_directory: natives_dir.to_str().unwrap(),
+        game_directory: game_dir.to_str().unwrap(),
+        assets_root: assets_folder.to_str().unwrap(),
+        assets_index_name: &launch.asset_index.id,
+    }
+    .mapping();
+
+    // authlib-injector must be loaded before everything
+    let mut jvm_args: Vec<String> = agent_args;
+    jvm_args.extend(vec![
+        lib_path_args.to_string(),
+        installer_args.to_string(),
+        client_args.to_string(),
     ]);
+    jvm_args.extend(version_jvm_args.iter().map(|x| apply_placeholders(x, &launch_arg_mapping)));
+    jvm_args.push(launch.main_class.clone()); // main class must be last one
 
+    debug!("The JVM arguments: {:?}", jvm_args);
 
     for i in jvm_args{
         command = command.arg(i);
     }
 
-    let mut spilt = launch.launch_args.split(' ');
-    while let Some(args) = spilt.next(){
-        let mapping = launch_arg_mapping.get(args);
-        let args = match mapping {
-            None => {args}
-            Some(mapping) => {mapping}
-        };
-        
-        command = command.arg(args)
+    for i in game_args.iter(){
+        command = command.arg(apply_placeholders(i, &launch_arg_mapping));
+    }
+
+    // the account doesn't own the game, and the legacy arguments don't have `--demo`
+    if auth.demo && !game_args.iter().any(|x| x == "--demo") {
+        info!("The account doesn't own the game, launching in demo mode");
+        command = command.arg("--demo");
     }
     
     // let (output,command_child)= shell
@@ -477,7 +571,10 @@ async fn running(
     
     let command_child:Arc<CommandChild> = command_child.into();
     
-    let status = Status::Running(command_child.clone());
+    let status = Status::Running{
+        child: command_child.clone(),
+        demo: auth.demo,
+    };
     
     map.update(&app,&id,status).await;
     
@@ -508,11 +605,11 @@ pub async fn launch_game(
         return Ok(());
     }
     
-    let running_result = {
+    let (running_result, natives_dir) = {
         
         let prepare_result = prepare(&id, &app, &map, &config).await;
         
-        let userid = config.read().await.activate_user_uuid.clone();
+        let userid = launch_user(&id, &app, &config).await;
 
         let _lock = lock.lock().await;
 
@@ -545,15 +642,24 @@ pub async fn launch_game(
         }
 
 
-        let running_result = running(&id, game_files, &app, &map, &launch_data,userid).await;
-        running_result
+        let natives_dir = match NATIVES_ROOT.to_path(&app) {
+            Ok(path) => path.join(format!("{}-{}", id, uuid::Uuid::new_v4())),
+            Err(details) => {
+                failed(&id, &app, details.to_string(), &map).await;
+                return Ok(());
+            }
+        };
+
+        let running_result = running(&id, game_files, &app, &map, &launch_data,userid,&natives_dir).await;
+        (running_result, natives_dir)
     };
-    
+
     let mut reciver = match running_result {
         Ok(event) => {
             event
         }
         Err(details) => {
+            clean_natives(&natives_dir).await;
             failed(&id,&app,details.to_string(),&map).await;
             return Ok(())
         }
@@ -583,6 +689,8 @@ pub async fn launch_game(
 
     }
 
+    clean_natives(&natives_dir).await;
+
     if status.unwrap_or(-1) == 0{
         map.update(&app,&id,Status::Stopped).await;
     } else{
@@ -598,7 +706,8 @@ pub async fn get_instance_status(
     map:State<'_,SafeInstanceStatus>
 ) -> CommandResult<StatusPayload>{
     let status = map.status_str(&id).await;
-    Ok(StatusPayload { status })
+    let demo = map.is_demo(&id).await;
+    Ok(StatusPayload { status, demo })
 }
 
 
@@ -1,197 +1,309 @@
-use crate::utils::minecraft::auth::{AuthFlow, MinecraftAuthError, MinecraftAuthStep, SafeAccountList};
+use crate::event::login::{login_finished, login_progress, LoginResultPayload};
+use crate::constant::PENDING_LOGIN;
+use crate::utils::minecraft::auth::{
+    DeviceCodeResponse, LoginSessions, MinecraftAuthStep, MinecraftAuthorizationFlow, PendingLogins,
+    SafeAccountList, SafePendingLogins,
+};
+use crate::utils::data::TimeSensitiveData;
+use crate::utils::result::{CommandResult, IntoTAResult};
 use log::warn;
-use serde_json::json;
-use tauri::{AppHandle, State};
-use crate::utils::config::Storage;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager, State};
+use tauri_plugin_shell::ShellExt;
+use tokio::sync::Mutex;
+use crate::utils::config::{Save, Storage};
 
-#[tauri::command]
-pub async fn devicecode_init(authflow_rwlock: State<'_, AuthFlow>) -> Result<String, String> {
-    if let MinecraftAuthStep::DeviceCode(var) = &authflow_rwlock.read().await.status {
-        if var.is_vaild() {
-            return Ok(json!({
-                "user_code": var.data.user_code,
-                "device_code": var.data.device_code,
-                "verification_uri": var.data.verification_uri,
-            })
-            .to_string());
+/// The running tasks of [login], keyed by the session id.
+pub type LoginTask = Mutex<HashMap<String, JoinHandle<()>>>;
+
+/// The device code shown to user, the user should open `verification_uri` and enter `user_code`.
+#[derive(Serialize, Debug)]
+pub struct DeviceCodeInfo {
+    /// The id of login session, which is passed to the commands of following steps.
+    pub session: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    /// RFC 3339 time when the code is expired.
+    pub expire_in: String,
+}
+
+impl DeviceCodeInfo {
+    fn new(session: &str, value: &TimeSensitiveData<DeviceCodeResponse>) -> Self {
+        Self {
+            session: session.to_string(),
+            user_code: value.data.user_code.clone(),
+            verification_uri: value.data.verification_uri.clone(),
+            expire_in: (value.time + value.data.expires_in).to_rfc3339(),
         }
     }
+}
 
-    {
-        let mut auth_flow = authflow_rwlock.write().await;
-        let response = &auth_flow.generate_device_code().await;
-        match response {
-            Ok(_) => {
-                if let MinecraftAuthStep::DeviceCode(var) = &auth_flow.status {
-                    if var.is_vaild() {
-                        let expire_in = var.time + var.data.expires_in;
-                        return Ok(json!({
-                            "user_code": var.data.user_code,
-                            "expire_in": expire_in.to_rfc3339(),
-                            "verification_uri": var.data.verification_uri,
-                        })
-                        .to_string());
-                    }
-                }
-                Err(json!({
-                    "status": "Failed to fetch the device code"
-                })
-                .to_string())
-            }
-            Err(_) => Err(json!({
-                "status": "Failed to fetch the device code"
-            })
-            .to_string()),
+/// The result of a successful login step, `description` tells what the next step is doing.
+#[derive(Serialize, Debug)]
+pub struct LoginStatus {
+    pub session: String,
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<&'static str>,
+}
+
+impl LoginStatus {
+    fn success(session: &str, description: &'static str) -> Self {
+        Self {
+            session: session.to_string(),
+            status: "success",
+            description: Some(description),
         }
     }
 }
 
+/// Start a new login session and generate the device code of it.
+async fn new_device_code(sessions: &LoginSessions) -> CommandResult<DeviceCodeInfo> {
+    let (session, flow) = sessions.create().await;
+    let mut flow = flow.lock().await;
+
+    if let Err(e) = flow.generate_device_code().await {
+        sessions.remove(&session).await;
+        return Err(e.into());
+    }
+
+    match &flow.status {
+        MinecraftAuthStep::DeviceCode(var) => Ok(DeviceCodeInfo::new(&session, var)),
+        _ => crate::bail!("Failed to fetch the device code"),
+    }
+}
+
 #[tauri::command]
-pub async fn devicecode_exchange(authflow_rwlock: State<'_, AuthFlow>) -> Result<String, String> {
-    loop {
-        {
-            let mut auth_flow = authflow_rwlock.write().await;
-            match &auth_flow.status {
-                MinecraftAuthStep::DeviceCode(var) => {
-                    if !var.is_vaild() {
-                        return Err(json!({
-                            "status": "error",
-                            "description": "The device code is expired"
-                        })
-                        .to_string());
-                    }
-                }
-                _ => {
-                    return Err(json!({
-                        "status": "error",
-                        "description": "The device code is not ready to exchange or status is not DeviceCode"
-                    }).to_string());
-                }
-            }
-            let res = auth_flow.exchange_device_code().await;
-            match res {
-                Ok(_) => {
-                    break;
-                }
-                Err(e) => {
-                    match e {
-                        MinecraftAuthError::AuthorizationPending => {} // just wait next time
-                        _ => {
-                            return Err(json!({
-                                "status": "error",
-                                "description": "Failed to exchange the device code"
-                            })
-                            .to_string());
-                        }
-                    }
-                }
+pub async fn devicecode_init(sessions: State<'_, LoginSessions>) -> CommandResult<DeviceCodeInfo> {
+    new_device_code(&sessions).await
+}
+
+#[tauri::command]
+pub async fn devicecode_exchange(sessions: State<'_, LoginSessions>, session: String) -> CommandResult<LoginStatus> {
+    sessions.get(&session).await?.lock().await.poll_device_code().await?;
+    Ok(LoginStatus::success(&session, "getting the Xbox Live Auth Token......"))
+}
+
+/// Unlike the device code, the session is created and finished the first step in one call,
+/// the id of session is in the returned status.
+#[tauri::command]
+pub async fn authcode_login(sessions: State<'_, LoginSessions>, app: AppHandle) -> CommandResult<LoginStatus> {
+    let (session, flow) = sessions.create().await;
+    let mut auth_flow = flow.lock().await;
+
+    let result: CommandResult<()> = async {
+        let listener = auth_flow.generate_authorization_url().await?;
+
+        if let MinecraftAuthStep::AuthorizationCode(data) = &auth_flow.status {
+            if let Err(e) = app.shell().open(&data.url, None) {
+                crate::bail!("Failed to open the browser: {}", e);
             }
         }
-        tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+
+        auth_flow.exchange_authorization_code(listener).await?;
+        Ok(())
     }
+    .await;
 
-    Ok(json!({
-        "status": "success",
-        "description": "getting the Xbox Live Auth Token......"
-    })
-    .to_string())
+    if result.is_err() {
+        sessions.remove(&session).await;
+    }
+    result?;
+
+    Ok(LoginStatus::success(&session, "getting the Xbox Live Auth Token......"))
 }
 
 #[tauri::command]
-pub async fn xbox_live_auth(authflow_rwlock: State<'_, AuthFlow>) -> Result<String, String> {
-    let response = {
-        let mut authflow = authflow_rwlock.write().await;
-        authflow.xbox_live_auth().await
-    };
-    match response {
-        Ok(_) => Ok(json!({
-            "status": "success",
-            "description": "Xbox Live Auth Success,Getting the XSTS token"
-        })
-        .to_string()),
-        Err(e) => Err(json!({
-            "status": "error",
-            "description": e.to_string()
-        })
-        .to_string()),
-    }
+pub async fn xbox_live_auth(sessions: State<'_, LoginSessions>, session: String) -> CommandResult<LoginStatus> {
+    sessions.get(&session).await?.lock().await.xbox_live_auth().await?;
+    Ok(LoginStatus::success(&session, "Xbox Live Auth Success,Getting the XSTS token"))
 }
 
 #[tauri::command]
-pub async fn xbox_xsts_auth(authflow_rwlock: State<'_, AuthFlow>) -> Result<String, String> {
-    let response = {
-        let mut authflow = authflow_rwlock.write().await;
-        authflow.xbox_security_auth().await
-    };
-    match response {
-        Ok(_) => Ok(json!({
-            "status": "success",
-            "description": "getting the token for Minecraft"
-        })
-        .to_string()),
-        Err(e) => Err(json!({
-            "status": "error",
-            "description": e.to_string()
-        })
-        .to_string()),
-    }
+pub async fn xbox_xsts_auth(sessions: State<'_, LoginSessions>, session: String) -> CommandResult<LoginStatus> {
+    sessions.get(&session).await?.lock().await.xbox_security_auth().await?;
+    Ok(LoginStatus::success(&session, "getting the token for Minecraft"))
 }
 
 #[tauri::command]
-pub async fn minecraft_token(authflow_rwlock: State<'_, AuthFlow>) -> Result<String, String> {
-    let response = {
-        let mut authflow = authflow_rwlock.write().await;
-        authflow.get_minecraft_token().await
-    };
-    match response {
-        Ok(_) => Ok(json!({
-            "status": "success",
-            "description": "checking the profile..."
-        })
-        .to_string()),
-        Err(e) => Err(json!({
-            "status": "error",
-            "description": e.to_string()
-        })
-        .to_string()),
-    }
+pub async fn minecraft_token(sessions: State<'_, LoginSessions>, session: String) -> CommandResult<LoginStatus> {
+    sessions.get(&session).await?.lock().await.get_minecraft_token().await?;
+    Ok(LoginStatus::success(&session, "checking the profile..."))
 }
 
 #[tauri::command]
 pub async fn minecraft_profile(
-    authflow_rwlock: State<'_, AuthFlow>,
+    sessions: State<'_, LoginSessions>,
     map: State<'_, SafeAccountList>,
     app: AppHandle,
-) -> Result<String, String> {
-    let response = {
-        let mut authflow = authflow_rwlock.write().await;
-        authflow.check_minecraft_profile().await
-    };
+    session: String,
+) -> CommandResult<LoginStatus> {
+    let login_data = sessions.get(&session).await?.lock().await.check_minecraft_profile().await?;
+    sessions.remove(&session).await;
 
-    match response {
-        Ok(login_data) => {
-            {
-                let mut user = map.write().await;
-                user.add(login_data.clone());
-            }
+    {
+        let mut user = map.write().await;
+        user.relogin(login_data.clone());
+    }
+
+    if let Err(e) = map.read().await.save_by_app(&app) {
+        warn!("{:?}", e)
+    }
+
+    let id = login_data.profile.id.clone();
+    crate::event::user::change_user(Some(id), &app).await;
+
+    Ok(LoginStatus {
+        session,
+        status: "success",
+        description: None,
+    })
+}
+
+/// Run the rest of device code login of the session in background, the progress is reported by
+/// `login_progress:{session}` event and the result by `login_finished:{session}` event.
+fn spawn_login(app: AppHandle, session: String, flow: Arc<Mutex<MinecraftAuthorizationFlow>>) -> JoinHandle<()> {
+    tauri::async_runtime::spawn(async move {
+        let mut flow = flow.lock_owned().await;
+        let result = flow.wait_for_login(|progress| login_progress(&app, &session, progress)).await;
+        drop(flow);
 
-            match map.read().await.save_by_app(&app){
-                Err(e) => warn!("{:?}",e),
-                _ => {}
+        // the task finishes under the same lock as cancel_login, so a cancelled login never adds the account,
+        // and the login finished before cancel_login is not reported as cancelled.
+        if app.state::<LoginTask>().lock().await.remove(&session).is_none() {
+            return;
+        }
+
+        let result = match result {
+            Ok(account) => {
+                let id = account.profile.id.clone();
+                let accounts = app.state::<SafeAccountList>();
+                {
+                    let mut accounts = accounts.write().await;
+                    accounts.relogin(account);
+                    if let Err(e) = accounts.save_by_app(&app) {
+                        warn!("{:?}", e)
+                    }
+                }
+
+                crate::event::user::change_user(Some(id.clone()), &app).await;
+                LoginResultPayload::Success { uuid: id }
             }
+            Err(e) => e.into(),
+        };
 
-            let id = login_data.profile.id.clone();
-            crate::event::user::change_user(Some(id), &app).await;
+        login_finished(&app, &session, result).await;
+        app.state::<LoginSessions>().remove(&session).await;
+        update_pending(&app, &session, None).await;
+    })
+}
+
+/// Save or remove the step of session in [PendingLogins].
+async fn update_pending(app: &AppHandle, session: &str, step: Option<MinecraftAuthStep>) {
+    let mut pending = app.state::<SafePendingLogins>().lock().await;
+    match step {
+        Some(step) => pending.0.insert(session.to_string(), step),
+        None => pending.0.remove(session),
+    };
 
-            Ok(json!({
-                "status": "success"
-            })
-            .to_string())
+    let result = PENDING_LOGIN.to_path(app).and_then(|path| {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
-        Err(e) => Err(json!({
-            "status": "error",
-            "description": e.to_string()
+        pending.save(&path)
+    });
+    if let Err(e) = result {
+        warn!("Failed to save the pending login: {:?}", e)
+    }
+}
+
+/// Resume the logins which are still waiting for user when app was closed, it is called when app starts.
+pub async fn resume_logins(app: &AppHandle) {
+    let mut pending = match PendingLogins::load_by_app(app) {
+        Ok(pending) => *pending,
+        Err(_) => PendingLogins::default(),
+    };
+    pending.retain_valid();
+
+    let device_codes: Vec<_> = pending
+        .0
+        .iter()
+        .filter_map(|(session, step)| match step {
+            MinecraftAuthStep::DeviceCode(data) => Some((session.clone(), data.clone())),
+            _ => None,
         })
-        .to_string()),
+        .collect();
+    app.manage(SafePendingLogins::new(pending));
+
+    let sessions = app.state::<LoginSessions>();
+    let tasks = app.state::<LoginTask>();
+    for (session, data) in device_codes {
+        let flow = sessions.resume(&session, MinecraftAuthStep::DeviceCode(data)).await;
+        tasks
+            .lock()
+            .await
+            .insert(session.clone(), spawn_login(app.clone(), session, flow));
+    }
+}
+
+/// Start a login session and run the rest of device code login in background, see [spawn_login].
+/// The session is saved, so it is resumed if app is closed before the user finished.
+#[tauri::command]
+pub async fn login(
+    sessions: State<'_, LoginSessions>,
+    tasks: State<'_, LoginTask>,
+    app: AppHandle,
+) -> CommandResult<DeviceCodeInfo> {
+    let info = new_device_code(&sessions).await?;
+    let session = info.session.clone();
+    let flow = sessions.get(&session).await?;
+
+    if let MinecraftAuthStep::DeviceCode(data) = &flow.lock().await.status {
+        update_pending(&app, &session, Some(MinecraftAuthStep::DeviceCode(data.clone()))).await;
     }
+
+    let mut tasks = tasks.lock().await;
+    tasks.insert(session.clone(), spawn_login(app, session, flow));
+
+    Ok(info)
+}
+
+/// Get the device code of the login resumed after restart, the UI can listen to its events again.
+/// The latest one is returned if there are many.
+#[tauri::command]
+pub async fn pending_login(pending: State<'_, SafePendingLogins>) -> CommandResult<Option<DeviceCodeInfo>> {
+    let pending = pending.lock().await;
+    let info = pending
+        .0
+        .iter()
+        .filter_map(|(session, step)| match step {
+            MinecraftAuthStep::DeviceCode(data) if data.is_vaild() => Some((session, data)),
+            _ => None,
+        })
+        .max_by_key(|(_, data)| data.time)
+        .map(|(session, data)| DeviceCodeInfo::new(session, data));
+
+    Ok(info)
+}
+
+#[tauri::command]
+pub async fn cancel_login(
+    sessions: State<'_, LoginSessions>,
+    tasks: State<'_, LoginTask>,
+    app: AppHandle,
+    session: String,
+) -> CommandResult<()> {
+    // the task which has finished is removed by itself, so it is not reported as cancelled
+    let handle = tasks.lock().await.remove(&session);
+    if let Some(handle) = handle {
+        handle.abort();
+        login_finished(&app, &session, LoginResultPayload::Cancelled).await;
+    }
+    sessions.remove(&session).await;
+    update_pending(&app, &session, None).await;
+
+    Ok(())
 }
@@ -1,19 +1,145 @@
 use crate::event::user::change_user;
 use crate::utils::config::{SafeNoLauncherConfig, Storage};
-use tauri::{AppHandle, State};
-use crate::utils::minecraft::auth::{MinecraftProfile, SafeAccountList};
-use crate::utils::result::CommandResult;
+use tauri::{AppHandle, Manager, State};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Serialize;
+use std::path::PathBuf;
+use crate::constant::CLIENT_ID;
+use crate::utils::minecraft::auth::{
+    Account, AccountMeta, AccountType, MinecraftAuthorizationFlow, MinecraftProfile, SafeAccountList, XboxProfile,
+};
+use crate::utils::minecraft::profile::{is_valid_name, NameAvailability, SkinVariant};
+use crate::utils::minecraft::skin::{AvatarKind, SkinCache};
+use crate::utils::minecraft::import::{import, read_file, ImportReport, ImportSource};
+use crate::utils::minecraft::yggdrasil::YggdrasilServer;
+use crate::utils::result::{CommandResult, IntoTAResult};
+
+#[derive(Serialize,Debug)]
+pub struct UserInfo {
+    #[serde(flatten)]
+    pub profile: MinecraftProfile,
+    pub account_type: AccountType,
+    pub needs_relogin: bool,
+    /// The account doesn't own the game, and it launches the game in demo mode.
+    pub demo: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xbox: Option<XboxProfile>,
+    #[serde(flatten)]
+    pub meta: AccountMeta,
+}
 
 #[tauri::command]
-pub async fn get_users(map: State<'_, SafeAccountList>) -> CommandResult<Vec<MinecraftProfile>> {
-    let list = map.read().await.0
-        .iter()
-        .map(|x| x.profile.clone())
+pub async fn get_users(map: State<'_, SafeAccountList>) -> CommandResult<Vec<UserInfo>> {
+    let list = map.read().await
+        .sorted()
+        .into_iter()
+        .map(|x| UserInfo {
+            profile: x.profile.clone(),
+            account_type: x.account_type.clone(),
+            needs_relogin: x.needs_relogin,
+            demo: x.is_demo(),
+            xbox: x.xbox.clone(),
+            meta: x.meta.clone(),
+        })
         .collect();
     
     Ok(list)
 }
 
+/// Set the nickname shown instead of the profile name, null or empty string removes it.
+#[tauri::command]
+pub async fn set_nickname(
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    id: String,
+    nickname: Option<String>,
+) -> CommandResult<()> {
+    {
+        let mut accounts = accounts.write().await;
+        let account = accounts.find(&id).ok_or(anyhow::anyhow!("no user found: {}", id))?;
+        account.set_nickname(nickname);
+        accounts.save_by_app(&app)?;
+    }
+
+    change_user(Some(id), &app).await;
+    Ok(())
+}
+
+/// Move the accounts of `ids` to the front of list in that order.
+#[tauri::command]
+pub async fn reorder_users(
+    accounts: State<'_, SafeAccountList>,
+    config: State<'_, SafeNoLauncherConfig>,
+    app: AppHandle,
+    ids: Vec<String>,
+) -> CommandResult<()> {
+    {
+        let mut accounts = accounts.write().await;
+        accounts.reorder(&ids)?;
+        accounts.save_by_app(&app)?;
+    }
+
+    // the active user is not changed, the event only tells the UI to show the new order
+    let current = config.read().await.activate_user_uuid.clone();
+    change_user(current, &app).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn add_offline_user(
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    name: String,
+) -> CommandResult<MinecraftProfile> {
+    crate::ensure!(is_valid_name(&name), "Invalid name: {}, only 3-16 characters of a-z, A-Z, 0-9 and _ are allowed", name);
+
+    let account = Account::offline(&name);
+    let profile = account.profile.clone();
+
+    {
+        let mut accounts = accounts.write().await;
+        accounts.add(account);
+        accounts.save_by_app(&app)?;
+    }
+
+    change_user(Some(profile.id.clone()), &app).await;
+    Ok(profile)
+}
+
+/// Login to a Yggdrasil server, `server` can be the API root or any url which has ALI header.
+#[tauri::command]
+pub async fn add_yggdrasil_user(
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    server: String,
+    username: String,
+    password: String,
+) -> CommandResult<MinecraftProfile> {
+    let server = YggdrasilServer::resolve(&server).await.map_err(anyhow::Error::from)?;
+    let (session, profile) = server.login(&username, &password).await.map_err(anyhow::Error::from)?;
+    let account = Account::yggdrasil(session, profile);
+    let profile = account.profile.clone();
+
+    {
+        let mut accounts = accounts.write().await;
+        accounts.relogin(account);
+        accounts.save_by_app(&app)?;
+    }
+
+    change_user(Some(profile.id.clone()), &app).await;
+    Ok(profile)
+}
+
+/// Sign out every session of the Yggdrasil account by the password, the accounts in list are kept,
+/// and they have to login again when the server refused their tokens.
+#[tauri::command]
+pub async fn signout_yggdrasil_user(server: String, username: String, password: String) -> CommandResult<()> {
+    let server = YggdrasilServer::resolve(&server).await.map_err(anyhow::Error::from)?;
+    server.signout(&username, &password).await.map_err(anyhow::Error::from)?;
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn get_current_user(current_user: State<'_, SafeNoLauncherConfig>) -> Result<String, String> {
     let current_user = current_user.read().await.activate_user_uuid.clone();
@@ -24,8 +150,11 @@ pub async fn get_current_user(current_user: State<'_, SafeNoLauncherConfig>) ->
 pub async fn set_current_user(
     current_user: State<'_, SafeNoLauncherConfig>,
     app: AppHandle,
+    accounts: State<'_, SafeAccountList>,
     id: String,
 ) -> CommandResult<String> {
+    crate::ensure!(accounts.read().await.get(&id).is_some(), "no user found: {}", id);
+
     let mut current_user = current_user.write().await;
     current_user.activate_user_uuid = Some(id.clone());
     change_user(Some(id), &app).await;
@@ -41,6 +170,15 @@ pub async fn logout_user(
     id: String,
 ) -> CommandResult<()> {
 
+    // the token on Yggdrasil server should be invalidated too, but we still logout if failed.
+    // The list is not locked during the request, so a slow server doesn't block the other commands.
+    let session = accounts.read().await.get(&id).and_then(|x| x.yggdrasil.clone());
+    if let Some(session) = session {
+        if let Err(e) = YggdrasilServer::new(&session.api_root).invalidate(&session).await {
+            log::warn!("Failed to invalidate the token: {}", e);
+        }
+    }
+
     {
         let mut accounts = accounts.write().await;
         accounts.remove(&id);
@@ -59,4 +197,223 @@ pub async fn logout_user(
 
     change_user(None, &app).await;
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Clone the account, so the request to Minecraft services is made without holding the lock of list.
+async fn clone_account(accounts: &SafeAccountList, id: &str) -> anyhow::Result<Account> {
+    accounts.read().await.get(id).cloned().ok_or(anyhow::anyhow!("no user found: {}", id))
+}
+
+/// Write the profile and tokens of the account changed by [clone_account] back to the list,
+/// and tell the UI the profile is changed.
+async fn update_account(accounts: &SafeAccountList, app: &AppHandle, changed: Account) -> CommandResult<MinecraftProfile> {
+    let id = changed.profile.id.clone();
+    let profile = changed.profile.clone();
+
+    {
+        let mut accounts = accounts.write().await;
+        let account = accounts.find(&id).ok_or(anyhow::anyhow!("no user found: {}", id))?;
+        account.profile = profile.clone();
+        account.update_tokens(changed);
+        accounts.save_by_app(app)?;
+    }
+
+    change_user(Some(id), app).await;
+    Ok(profile)
+}
+
+/// Upload the skin from a PNG file, `path` is the path of file on this computer.
+#[tauri::command]
+pub async fn upload_skin(
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    id: String,
+    variant: SkinVariant,
+    path: String,
+) -> CommandResult<MinecraftProfile> {
+    let png = tokio::fs::read(&path).await?;
+
+    let mut account = clone_account(&accounts, &id).await?;
+    account.upload_skin(&MinecraftAuthorizationFlow::new(CLIENT_ID), variant, png).await?;
+    update_account(&accounts, &app, account).await
+}
+
+#[tauri::command]
+pub async fn set_skin_url(
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    id: String,
+    variant: SkinVariant,
+    url: String,
+) -> CommandResult<MinecraftProfile> {
+    let mut account = clone_account(&accounts, &id).await?;
+    account.set_skin_url(&MinecraftAuthorizationFlow::new(CLIENT_ID), variant, &url).await?;
+    update_account(&accounts, &app, account).await
+}
+
+#[tauri::command]
+pub async fn reset_skin(
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    id: String,
+) -> CommandResult<MinecraftProfile> {
+    let mut account = clone_account(&accounts, &id).await?;
+    account.reset_skin(&MinecraftAuthorizationFlow::new(CLIENT_ID)).await?;
+    update_account(&accounts, &app, account).await
+}
+
+/// Show the cape with `cape_id`, or hide the cape when `cape_id` is null.
+#[tauri::command]
+pub async fn set_cape(
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    id: String,
+    cape_id: Option<String>,
+) -> CommandResult<MinecraftProfile> {
+    let flow = MinecraftAuthorizationFlow::new(CLIENT_ID);
+    let mut account = clone_account(&accounts, &id).await?;
+    match cape_id {
+        Some(cape_id) => account.show_cape(&flow, &cape_id).await?,
+        None => account.hide_cape(&flow).await?,
+    }
+    update_account(&accounts, &app, account).await
+}
+
+#[tauri::command]
+pub async fn refresh_profile(
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    id: String,
+) -> CommandResult<MinecraftProfile> {
+    let mut account = clone_account(&accounts, &id).await?;
+    account.refresh_profile(&MinecraftAuthorizationFlow::new(CLIENT_ID)).await?;
+    update_account(&accounts, &app, account).await
+}
+
+/// Get the avatar of user as a `data:` url of PNG, it is rendered from the active skin in the cache,
+/// so it still works without network after the first time. `None` if the user has no skin.
+#[tauri::command]
+pub async fn get_avatar(
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    id: String,
+    kind: AvatarKind,
+    size: u32,
+) -> CommandResult<Option<String>> {
+    let skin = {
+        let accounts = accounts.read().await;
+        let account = accounts.get(&id).ok_or(anyhow::anyhow!("no user found: {}", id))?;
+        account.profile.skins.iter().find(|x| x.state == "ACTIVE").cloned()
+    };
+
+    let Some(skin) = skin else {
+        return Ok(None);
+    };
+
+    let avatar = SkinCache::from_app(&app)?.avatar(&skin, kind, size).await?;
+    Ok(Some(format!("data:image/png;base64,{}", STANDARD.encode(avatar))))
+}
+
+#[tauri::command]
+pub async fn check_name(
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    id: String,
+    name: String,
+) -> CommandResult<NameAvailability> {
+    let mut account = clone_account(&accounts, &id).await?;
+    let tokens = account.access_tokens();
+    let availability = account.check_name(&MinecraftAuthorizationFlow::new(CLIENT_ID), &name).await?;
+
+    // only the tokens may be refreshed by the check
+    if account.access_tokens() != tokens {
+        let mut accounts = accounts.write().await;
+        if let Some(stored) = accounts.find(&id) {
+            stored.update_tokens(account);
+        }
+        accounts.save_by_app(&app)?;
+    }
+
+    Ok(availability)
+}
+
+#[tauri::command]
+pub async fn change_name(
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    id: String,
+    name: String,
+) -> CommandResult<MinecraftProfile> {
+    let mut account = clone_account(&accounts, &id).await?;
+    account.change_name(&MinecraftAuthorizationFlow::new(CLIENT_ID), &name).await?;
+    update_account(&accounts, &app, account).await
+}
+
+#[tauri::command]
+pub async fn get_account_encryption(config: State<'_, SafeNoLauncherConfig>) -> CommandResult<bool> {
+    Ok(config.read().await.encrypt_accounts)
+}
+
+/// Turn the encryption of the accounts file on or off, the file is rewritten in the new format at once.
+#[tauri::command]
+pub async fn set_account_encryption(
+    config: State<'_, SafeNoLauncherConfig>,
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    enable: bool,
+) -> CommandResult<()> {
+    // hold the lock, so the file is not saved by others while migrating
+    let mut accounts = accounts.write().await;
+    accounts.set_encryption_by_app(&app, enable)?;
+
+    let mut config = config.write().await;
+    config.encrypt_accounts = enable;
+    config.save_by_app(&app)?;
+    Ok(())
+}
+
+/// The accounts file of other launcher in its default folder.
+fn import_path(app: &AppHandle, source: ImportSource) -> anyhow::Result<PathBuf> {
+    let path = match source {
+        ImportSource::Prism => app.path().data_dir()?.join("PrismLauncher").join("accounts.json"),
+        ImportSource::Vanilla => {
+            let minecraft = if cfg!(target_os = "windows") {
+                app.path().data_dir()?.join(".minecraft")
+            } else if cfg!(target_os = "macos") {
+                app.path().data_dir()?.join("minecraft")
+            } else {
+                app.path().home_dir()?.join(".minecraft")
+            };
+
+            // the launcher from Microsoft Store uses another file
+            let store = minecraft.join("launcher_accounts_microsoft_store.json");
+            if store.exists() {
+                store
+            } else {
+                minecraft.join("launcher_accounts.json")
+            }
+        }
+    };
+    Ok(path)
+}
+
+/// Import the accounts from other launcher, `path` is the accounts file, or the default one when it is None.
+/// Every account is refreshed before adding, the result of each account is reported.
+#[tauri::command]
+pub async fn import_accounts(
+    accounts: State<'_, SafeAccountList>,
+    app: AppHandle,
+    source: ImportSource,
+    path: Option<String>,
+) -> CommandResult<Vec<ImportReport>> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => import_path(&app, source)?,
+    };
+    let candidates = read_file(source, &path).map_err(anyhow::Error::from)?;
+
+    let reports = import(&accounts, candidates, &MinecraftAuthorizationFlow::new(CLIENT_ID)).await;
+    accounts.read().await.save_by_app(&app)?;
+
+    Ok(reports)
+}
@@ -7,8 +7,15 @@ pub const NOLAUNCHER_CONFIG_FILE: SavePath = SavePath::Config(&["config.json"]);
 pub const ACCOUNTS_DATA:SavePath = SavePath::Config(&["accounts.json"]);
 pub const LIB_PATH:SavePath = SavePath::Config(&["libraries"]);
 pub const CACHED_DEFAULT:SavePath = SavePath::Cache(&[]);
+pub const NATIVES_ROOT:SavePath = SavePath::Cache(&["natives"]);
+pub const SKIN_CACHE:SavePath = SavePath::Cache(&["skins"]);
+pub const PENDING_LOGIN:SavePath = SavePath::Cache(&["pending_login.json"]);
 pub const ASSET_ROOT:SavePath = SavePath::Config(&["assets"]);
 pub const ASSET_INDEX_ROOT:SavePath = SavePath::Config(&["assets","indexes"]);
 pub const ASSET_OBJECT_ROOT:SavePath = SavePath::Config(&["assets","objects"]);
+pub const AUTHLIB_INJECTOR_PATH:SavePath = SavePath::Config(&["authlib-injector"]);
 
 pub const NO_SIZE_DEFAULT_SIZE:i64 = 100000;
+
+/// The Microsoft (Azure) application id used by the login flow, it is also passed to the game as `${clientid}`.
+pub const CLIENT_ID: &str = env!("MICROSOFT_CLIENT_ID");
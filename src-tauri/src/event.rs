@@ -1,2 +1,3 @@
 pub mod user;
 pub mod instance;
+pub mod login;
@@ -5,7 +5,9 @@ use crate::utils::minecraft::instance::Status;
 
 #[derive(Clone, serde::Serialize)]
 pub struct StatusPayload {
-    pub status: String
+    pub status: String,
+    /// The game is running in demo mode, because the account doesn't own the game.
+    pub demo: bool
 }
 
 #[derive(Clone, serde::Serialize)]
@@ -15,8 +17,10 @@ struct ProgressPayload {
 }
 pub async fn instance_status_update(app: &AppHandle,id:&str,status:&Status) {
     
+    let demo = matches!(status, Status::Running { demo: true, .. });
+    
     let status = match status {
-        Status::Running(_) => {"Running"}
+        Status::Running { .. } => {"Running"}
         Status::Preparing => {"Preparing"}
         Status::Checking { .. } => {"Checking"}
         Status::Downloading { .. } => {"Downloading"}
@@ -24,7 +28,7 @@ pub async fn instance_status_update(app: &AppHandle,id:&str,status:&Status) {
         Status::Failed { .. } => {"Failed"}
     }.to_string();
     
-    app.emit(&format!("instance_status_update:{id}"), StatusPayload { status })
+    app.emit(&format!("instance_status_update:{id}"), StatusPayload { status, demo })
         .unwrap()
 }
 
@@ -0,0 +1,35 @@
+use tauri::{AppHandle, Manager};
+use crate::utils::minecraft::auth::{LoginProgress, MinecraftAuthError, ResponseDetails};
+
+/// The result of the login task started by [crate::command::login::login].
+#[derive(Clone, serde::Serialize)]
+#[serde(tag = "status")]
+pub enum LoginResultPayload {
+    Success { uuid: String },
+    Failed {
+        error: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        details: Option<ResponseDetails>,
+    },
+    Cancelled,
+}
+
+impl From<MinecraftAuthError> for LoginResultPayload {
+    fn from(value: MinecraftAuthError) -> Self {
+        Self::Failed {
+            error: value.to_string(),
+            details: value.details().cloned(),
+        }
+    }
+}
+
+/// It is called inside the login flow, which can't wait, so it is not async.
+pub fn login_progress(app: &AppHandle, session: &str, progress: LoginProgress) {
+    app.emit(&format!("login_progress:{session}"), progress)
+        .unwrap()
+}
+
+pub async fn login_finished(app: &AppHandle, session: &str, result: LoginResultPayload) {
+    app.emit(&format!("login_finished:{session}"), result)
+        .unwrap()
+}
@@ -9,3 +9,14 @@ pub async fn change_user(uuid: Option<String>, app: &AppHandle) {
     app.emit("change_user", UUIDPayload { uuid: uuid.clone() })
         .unwrap()
 }
+
+#[derive(Clone, serde::Serialize)]
+struct NeedsReloginPayload {
+    pub uuids: Vec<String>,
+}
+
+/// Tell the UI which accounts have to login again, all of them are sent every time.
+pub async fn needs_relogin(uuids: Vec<String>, app: &AppHandle) {
+    app.emit("needs_relogin", NeedsReloginPayload { uuids })
+        .unwrap()
+}
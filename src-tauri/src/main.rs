@@ -3,17 +3,26 @@
 
 use std::collections::HashMap;
 use crate::command::login::{
-    devicecode_exchange, devicecode_init, minecraft_profile, minecraft_token, xbox_live_auth,
-    xbox_xsts_auth,
+    authcode_login, cancel_login, devicecode_exchange, devicecode_init, login, minecraft_profile,
+    minecraft_token, pending_login, resume_logins, xbox_live_auth, xbox_xsts_auth, LoginTask,
+};
+use crate::command::user::{
+    add_offline_user, add_yggdrasil_user, change_name, check_name, get_account_encryption, get_avatar, get_current_user,
+    get_users, import_accounts, logout_user, refresh_profile, reorder_users, reset_skin, set_account_encryption,
+    set_cape, set_current_user, set_nickname, set_skin_url, signout_yggdrasil_user, upload_skin,
 };
-use crate::command::user::{get_current_user, get_users, logout_user, set_current_user};
 use crate::utils::config::{NoLauncherConfig, Storage};
 use log::{LevelFilter, Log, Metadata, Record};
-use tauri::Manager;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
 use tokio::sync::{Mutex, RwLock};
-use crate::command::instance::{create_instance, list_instance, list_versions, launch_game, get_instance_status};
-use crate::utils::minecraft::auth::{AccountList, AuthFlow, MinecraftAuthorizationFlow};
+use crate::command::instance::{
+    create_instance, list_instance, list_versions, launch_game, get_instance_status, set_instance_account,
+};
+use crate::utils::minecraft::auth::{AccountList, LoginSessions, MinecraftAuthorizationFlow, SafeAccountList};
+use crate::utils::minecraft::health::HealthChange;
 use crate::utils::minecraft::instance::{InstanceLock, SafeInstanceStatus};
+use crate::constant::CLIENT_ID;
 
 mod command;
 mod event;
@@ -44,7 +53,54 @@ fn init_log() {
     log::set_logger(&LOGGER).unwrap();
 }
 
-const CLIENT_ID: &str = env!("MICROSOFT_CLIENT_ID");
+/// How often the accounts are checked in background.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Check every account periodically, so the UI can tell the user to login again before launching.
+async fn check_accounts(app: AppHandle) {
+    let flow = MinecraftAuthorizationFlow::new(CLIENT_ID);
+    let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        let accounts = app.state::<SafeAccountList>();
+        let ids: Vec<String> = accounts.read().await.0.iter().map(|x| x.profile.id.clone()).collect();
+
+        // check a clone of each account, so the list is not locked during the requests
+        let mut changed = HealthChange::default();
+        for id in ids {
+            let Some(mut account) = accounts.read().await.get(&id).cloned() else {
+                continue;
+            };
+            let change = account.update_health(&flow).await;
+            if !change.any() {
+                continue;
+            }
+
+            changed.needs_relogin |= change.needs_relogin;
+            changed.tokens |= change.tokens;
+            // the account may be removed during the check
+            if let Some(stored) = accounts.write().await.find(&id) {
+                stored.needs_relogin = account.needs_relogin;
+                stored.update_tokens(account);
+            }
+        }
+
+        if !changed.any() {
+            continue;
+        }
+
+        let accounts = accounts.read().await;
+        if let Err(e) = accounts.save_by_app(&app) {
+            log::warn!("{:?}", e)
+        }
+
+        if changed.needs_relogin {
+            let uuids = accounts.0.iter().filter(|x| x.needs_relogin).map(|x| x.profile.id.clone()).collect();
+            event::user::needs_relogin(uuids, &app).await;
+        }
+    }
+}
 
 fn main() {
     
@@ -52,7 +108,7 @@ fn main() {
         init_log();
     }
     
-    let authflow = AuthFlow::new(MinecraftAuthorizationFlow::new(CLIENT_ID));
+    let login_sessions = LoginSessions::new(MinecraftAuthorizationFlow::new(CLIENT_ID));
     let builder = tauri::Builder::default();
 
     #[cfg(debug_assertions)]
@@ -60,49 +116,73 @@ fn main() {
     let instance_status:SafeInstanceStatus = HashMap::new().into();
     let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(6).enable_io().enable_time().build().unwrap();
     let lock: InstanceLock = Mutex::new(());
+    let login_task: LoginTask = Mutex::new(HashMap::new());
 
     builder
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_shell::init())
-        .manage(authflow)
+        .manage(login_sessions)
         .manage(instance_status)
         .manage(runtime)
         .manage(lock)
+        .manage(login_task)
         .invoke_handler(tauri::generate_handler![
             devicecode_init,
             devicecode_exchange,
+            authcode_login,
             xbox_live_auth,
             xbox_xsts_auth,
             minecraft_token,
             minecraft_profile,
+            login,
+            cancel_login,
+            pending_login,
             get_users,
             get_current_user,
             set_current_user,
             logout_user,
+            add_offline_user,
+            add_yggdrasil_user,
+            signout_yggdrasil_user,
+            upload_skin,
+            set_skin_url,
+            reset_skin,
+            set_cape,
+            refresh_profile,
+            get_avatar,
+            check_name,
+            change_name,
+            get_account_encryption,
+            set_account_encryption,
+            import_accounts,
+            set_nickname,
+            reorder_users,
             list_versions,
             create_instance,
             list_instance,
             launch_game,
-            get_instance_status
+            get_instance_status,
+            set_instance_account
         ])
         .setup(|app| {
             let handle = app.handle();
             tauri::async_runtime::block_on(async move {
                 
-                match NoLauncherConfig::load_by_app(&handle){
-                    Ok(config) => {
-                        let data = RwLock::new(*config);
-                        handle.manage(data);
-                    }
+                let config = match NoLauncherConfig::load_by_app(&handle){
+                    Ok(config) => *config,
                     Err(e) => {
                         log::error!("Failed to load the config,: {}", e);
-                        handle.manage(RwLock::new(NoLauncherConfig::default()));
+                        NoLauncherConfig::default()
                     }
-                }
+                };
 
-                let account_list = RwLock::new(*AccountList::load_by_app(&handle).unwrap_or(Box::new(AccountList::default())));
+                let account_list = RwLock::new(AccountList::init_by_app(&handle, config.encrypt_accounts));
+                handle.manage(RwLock::new(config));
                 handle.manage(account_list);
 
+                resume_logins(&handle).await;
+                tauri::async_runtime::spawn(check_accounts(handle.clone()));
+
             });
             Ok(())
         })
@@ -1,4 +1,5 @@
 pub mod config;
+pub mod crypto;
 pub mod data;
 pub mod minecraft;
 pub mod result;
@@ -16,7 +16,10 @@ pub struct NoLauncherConfig {
     #[serde(default)]
     pub metadata_setting: MetadataSetting,
     #[serde(default)]
-    pub instances:Vec<PathBuf>
+    pub instances:Vec<PathBuf>,
+    /// Seal the accounts file by a key file, the file is migrated when this is changed.
+    #[serde(default)]
+    pub encrypt_accounts: bool,
 }
 
 pub type SafeNoLauncherConfig = RwLock<NoLauncherConfig>;
@@ -0,0 +1,103 @@
+use std::io::Write;
+use std::path::Path;
+use anyhow::{anyhow, ensure, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+/// The header of a sealed file, followed by the nonce and the ciphertext,
+/// so a sealed file can be told from the plain JSON we wrote before.
+const MAGIC: &[u8] = b"NOLAUNCHER-SEALED-1\n";
+const NONCE_SIZE: usize = 12;
+const KEY_SIZE: usize = 32;
+
+pub fn is_sealed(content: &[u8]) -> bool {
+    content.starts_with(MAGIC)
+}
+
+/// Encrypt `plain` by ChaCha20-Poly1305 with a random nonce.
+pub fn seal(key: &Key, plain: &[u8]) -> Result<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let cipher = ChaCha20Poly1305::new(key)
+        .encrypt(&nonce, plain)
+        .map_err(|_| anyhow!("Failed to encrypt the data"))?;
+
+    let mut content = MAGIC.to_vec();
+    content.extend_from_slice(&nonce);
+    content.extend(cipher);
+    Ok(content)
+}
+
+pub fn open(key: &Key, content: &[u8]) -> Result<Vec<u8>> {
+    let content = content.strip_prefix(MAGIC).ok_or(anyhow!("The data is not sealed"))?;
+    ensure!(content.len() >= NONCE_SIZE, "The sealed data is broken");
+
+    let (nonce, cipher) = content.split_at(NONCE_SIZE);
+    ChaCha20Poly1305::new(key)
+        .decrypt(Nonce::from_slice(nonce), cipher)
+        .map_err(|_| anyhow!("Failed to decrypt the data, the key is wrong or the data is broken"))
+}
+
+pub fn read_key(path: &Path) -> Result<Key> {
+    let content = std::fs::read(path)?;
+    ensure!(content.len() == KEY_SIZE, "The key file {} is broken", path.display());
+    Ok(*Key::from_slice(&content))
+}
+
+/// Generate a new key to `path`, the file is only readable by the current user on unix.
+pub fn create_key(path: &Path) -> Result<Key> {
+    let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(&key)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod test {
+    use chacha20poly1305::aead::{KeyInit, OsRng};
+    use chacha20poly1305::ChaCha20Poly1305;
+    use crate::utils::crypto::{create_key, is_sealed, open, read_key, seal};
+
+    #[test]
+    fn test_seal() {
+        let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        let sealed = seal(&key, b"refresh token").unwrap();
+
+        assert!(is_sealed(&sealed));
+        assert!(!is_sealed(b"[]"));
+        assert_eq!(open(&key, &sealed).unwrap(), b"refresh token");
+
+        let other = ChaCha20Poly1305::generate_key(&mut OsRng);
+        assert!(open(&other, &sealed).is_err());
+        assert!(open(&key, &sealed[..sealed.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_key_file() {
+        let folder = std::env::temp_dir().join(format!("nolauncher-key-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("accounts.key");
+
+        let key = create_key(&path).unwrap();
+        assert_eq!(read_key(&path).unwrap(), key);
+        // never overwrite the key, or the sealed data is lost
+        assert!(create_key(&path).is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -42,6 +42,14 @@ where
             .expect("Failed to convert chrono::Duration to std::Duration");
         duration < self.data.get_duration()
     }
+
+    /// Whether the data is expired or will be expired in `margin`.
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        let duration = (Local::now() - self.time)
+            .to_std()
+            .expect("Failed to convert chrono::Duration to std::Duration");
+        duration + margin >= self.data.get_duration()
+    }
 }
 
 fn str_to_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Local>, D::Error> {
@@ -1,3 +1,12 @@
 pub mod auth;
 pub mod instance;
-pub mod metadata;
\ No newline at end of file
+pub mod metadata;
+pub mod yggdrasil;
+pub mod profile;
+pub mod skin;
+pub mod import;
+pub mod health;
+pub mod source;
+pub mod piston;
+pub mod arguments;
+pub mod natives;
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use crate::utils::minecraft::auth::LaunchAuth;
+use crate::utils::minecraft::metadata::{rules_analyzer_for, Environment, Rule};
+
+/// The features of launch, which are checked by the `features` of [Rule] with [Environment].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Features {
+    pub is_demo_user: bool,
+    pub has_custom_resolution: bool,
+    pub has_quick_plays_support: bool,
+    pub is_quick_play_singleplayer: bool,
+    pub is_quick_play_multiplayer: bool,
+    pub is_quick_play_realms: bool,
+}
+
+impl Features {
+    /// The unknown feature is always false.
+    pub fn get(&self, name: &str) -> bool {
+        match name {
+            "is_demo_user" => self.is_demo_user,
+            "has_custom_resolution" => self.has_custom_resolution,
+            "has_quick_plays_support" => self.has_quick_plays_support,
+            "is_quick_play_singleplayer" => self.is_quick_play_singleplayer,
+            "is_quick_play_multiplayer" => self.is_quick_play_multiplayer,
+            "is_quick_play_realms" => self.is_quick_play_realms,
+            _ => false,
+        }
+    }
+}
+
+/// An item of `arguments.game` or `arguments.jvm` since 1.13, the values with rules are only
+/// used when the rules allow them.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Argument {
+    Plain(String),
+    Ruled { rules: Vec<Rule>, value: ArgumentValue },
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ArgumentValue {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Argument {
+    pub fn resolve(&self, env: &Environment) -> Vec<String> {
+        match self {
+            Argument::Plain(value) => vec![value.clone()],
+            Argument::Ruled { rules, value } => {
+                if !rules_analyzer_for(rules, env) {
+                    return vec![];
+                }
+                match value {
+                    ArgumentValue::One(value) => vec![value.clone()],
+                    ArgumentValue::Many(values) => values.clone(),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Default)]
+pub struct Arguments {
+    #[serde(default)]
+    pub game: Vec<Argument>,
+    #[serde(default)]
+    pub jvm: Vec<Argument>,
+}
+
+impl Arguments {
+    /// The `minecraftArguments` before 1.13, which is split by space, and it doesn't have jvm arguments.
+    pub fn from_legacy(arguments: &str) -> Self {
+        Self {
+            game: arguments
+                .split(' ')
+                .filter(|x| !x.is_empty())
+                .map(|x| Argument::Plain(x.to_string()))
+                .collect(),
+            jvm: vec![],
+        }
+    }
+}
+
+/// Resolve the arguments by their rules, which are checked with `env`.
+pub fn resolve(arguments: &[Argument], env: &Environment) -> Vec<String> {
+    arguments.iter().flat_map(|x| x.resolve(env)).collect()
+}
+
+/// The values known when launching, which fill the placeholders of arguments, see [LaunchPlaceholders::mapping].
+pub struct LaunchPlaceholders<'a> {
+    pub auth: &'a LaunchAuth,
+    pub client_id: &'a str,
+    /// The id of Minecraft version, like `1.8.9`.
+    pub version_name: &'a str,
+    pub version_type: &'a str,
+    pub classpath: &'a str,
+    pub natives_directory: &'a str,
+    pub game_directory: &'a str,
+    pub assets_root: &'a str,
+    pub assets_index_name: &'a str,
+}
+
+impl LaunchPlaceholders<'_> {
+    /// The mapping for [apply_placeholders], it has the placeholders of legacy `minecraftArguments` too.
+    pub fn mapping(&self) -> HashMap<&'static str, String> {
+        let auth = self.auth;
+        HashMap::from([
+            ("${classpath}", self.classpath.to_string()),
+            ("${natives_directory}", self.natives_directory.to_string()),
+            ("${launcher_name}", "NOLauncher".to_string()),
+            ("${launcher_version}", env!("CARGO_PKG_VERSION").to_string()),
+            ("${version_name}", self.version_name.to_string()),
+            ("${version_type}", self.version_type.to_string()),
+            ("${game_directory}", self.game_directory.to_string()),
+            ("${assets_root}", self.assets_root.to_string()),
+            ("${assets_index_name}", self.assets_index_name.to_string()),
+            // the virtual assets of the versions before 1.7 are not built yet, the assets root is used
+            ("${game_assets}", self.assets_root.to_string()),
+            ("${auth_player_name}", auth.name.clone()),
+            ("${auth_access_token}", auth.access_token.clone()),
+            ("${auth_session}", format!("token:{}:{}", auth.access_token, auth.uuid)),
+            ("${auth_uuid}", auth.uuid.clone()),
+            ("${auth_xuid}", auth.xuid.clone()),
+            ("${clientid}", self.client_id.to_string()),
+            ("${user_type}", auth.user_type.to_string()),
+            // the game before 1.13 parses it as json, so it can't be left as it is
+            ("${user_properties}", "{}".to_string()),
+        ])
+    }
+}
+
+/// Replace the `${name}` in argument by `mapping`, the unknown one is kept.
+pub fn apply_placeholders<V: AsRef<str>>(argument: &str, mapping: &HashMap<&str, V>) -> String {
+    let mut result = String::new();
+    let mut rest = argument;
+
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let key = &rest[start..start + end + 1];
+        result.push_str(&rest[..start]);
+        result.push_str(mapping.get(key).map(|x| x.as_ref()).unwrap_or(key));
+        rest = &rest[start + end + 1..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use serde_json::json;
+    use crate::utils::minecraft::arguments::{apply_placeholders, resolve, Argument, Arguments, Features};
+    use crate::utils::minecraft::metadata::Environment;
+
+    #[test]
+    fn test_resolve() {
+        let arguments: Vec<Argument> = serde_json::from_value(json!([
+            "--username",
+            "${auth_player_name}",
+            {
+                "rules": [{ "action": "allow", "features": { "is_demo_user": true } }],
+                "value": "--demo"
+            },
+            {
+                "rules": [{ "action": "allow", "features": { "has_custom_resolution": true } }],
+                "value": ["--width", "${resolution_width}", "--height", "${resolution_height}"]
+            }
+        ]))
+        .unwrap();
+
+        assert_eq!(resolve(&arguments, &Environment::host()), ["--username", "${auth_player_name}"]);
+
+        let features = Features {
+            is_demo_user: true,
+            has_custom_resolution: true,
+            ..Default::default()
+        };
+        let env = Environment { features, ..Environment::host() };
+        assert_eq!(
+            resolve(&arguments, &env),
+            ["--username", "${auth_player_name}", "--demo", "--width", "${resolution_width}", "--height", "${resolution_height}"]
+        );
+    }
+
+    #[test]
+    fn test_legacy() {
+        let arguments = Arguments::from_legacy("--username ${auth_player_name}  --version ${version_name}");
+        assert_eq!(
+            resolve(&arguments.game, &Environment::host()),
+            ["--username", "${auth_player_name}", "--version", "${version_name}"]
+        );
+        assert!(arguments.jvm.is_empty());
+    }
+
+    #[test]
+    fn test_apply_placeholders() {
+        let mapping = HashMap::from([("${natives_directory}", "/tmp/natives"), ("${launcher_name}", "NOLauncher")]);
+
+        assert_eq!(apply_placeholders("-Djava.library.path=${natives_directory}", &mapping), "-Djava.library.path=/tmp/natives");
+        assert_eq!(apply_placeholders("${launcher_name}-${launcher_version}", &mapping), "NOLauncher-${launcher_version}");
+        assert_eq!(apply_placeholders("${broken", &mapping), "${broken");
+        assert_eq!(apply_placeholders("--demo", &mapping), "--demo");
+    }
+}
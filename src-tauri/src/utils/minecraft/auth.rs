@@ -1,29 +1,76 @@
 use crate::utils::data::TimeSensitiveData;
 use anyhow::Result;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use md5::Md5;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
 use reqwest::header::{CONTENT_TYPE};
-use reqwest::Client;
+use reqwest::{Client, Response, Url};
+use sha2::{Digest, Sha256};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::DeserializeOwned;
 use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::{Mutex, RwLock};
+use std::path::{Path, PathBuf};
+use chacha20poly1305::Key;
 use nolauncher_derive::{Load, Save, Storage, TimeSensitive};
-use crate::constant::ACCOUNTS_DATA;
+use tauri::AppHandle;
+use crate::constant::{ACCOUNTS_DATA, PENDING_LOGIN};
 use crate::utils::config::Save;
 use crate::utils::config::Load;
+use crate::utils::crypto;
+use crate::utils::minecraft::yggdrasil::{YggdrasilError, YggdrasilProfile, YggdrasilServer, YggdrasilSession};
+use crate::utils::minecraft::profile::is_valid_name;
 
 
-const DEVICECODE_URL: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0/devicecode";
-const TOKEN_URL: &str = "https://login.microsoftonline.com/consumers/oauth2/v2.0/token";
-const MINECRAFT_LOGIN_WITH_XBOX: &str =
-    "https://api.minecraftservices.com/authentication/login_with_xbox";
-const XBOX_USER_AUTHENTICATE: &str = "https://user.auth.xboxlive.com/user/authenticate";
-const XBOX_XSTS_AUTHORIZE: &str = "https://xsts.auth.xboxlive.com/xsts/authorize";
-const MINECRAFT_PROFILE: &str = "https://api.minecraftservices.com/minecraft/profile";
+/// The endpoints used in the flow, they can be changed by [MinecraftAuthorizationFlow::with_endpoints],
+/// for example, pointing the whole flow to a local mock server when testing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthEndpoints {
+    pub device_code: String,
+    pub authorize: String,
+    pub token: String,
+    pub xbox_user_authenticate: String,
+    pub xbox_xsts_authorize: String,
+    pub minecraft_login_with_xbox: String,
+    pub minecraft_entitlements: String,
+    pub minecraft_profile: String,
+}
+
+impl Default for AuthEndpoints {
+    fn default() -> Self {
+        Self {
+            device_code: "https://login.microsoftonline.com/consumers/oauth2/v2.0/devicecode".to_string(),
+            authorize: "https://login.microsoftonline.com/consumers/oauth2/v2.0/authorize".to_string(),
+            token: "https://login.microsoftonline.com/consumers/oauth2/v2.0/token".to_string(),
+            xbox_user_authenticate: "https://user.auth.xboxlive.com/user/authenticate".to_string(),
+            xbox_xsts_authorize: "https://xsts.auth.xboxlive.com/xsts/authorize".to_string(),
+            minecraft_login_with_xbox: "https://api.minecraftservices.com/authentication/login_with_xbox".to_string(),
+            minecraft_entitlements: "https://api.minecraftservices.com/entitlements/mcstore".to_string(),
+            minecraft_profile: "https://api.minecraftservices.com/minecraft/profile".to_string(),
+        }
+    }
+}
+
 const SCOPE: &str = "XboxLive.signin offline_access";
 const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+const REFRESH_GRANT_TYPE: &str = "refresh_token";
+const AUTHORIZATION_CODE_GRANT_TYPE: &str = "authorization_code";
+/// How long we wait for the browser to redirect back to our loopback listener.
+const AUTHORIZATION_CODE_TIMEOUT: Duration = Duration::from_secs(300);
+/// How long a connection to the loopback listener can take to send its request, so an idle one doesn't block the others.
+const REDIRECT_READ_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// It is serializable, so the step waiting for user can be saved and resumed after restart, see [PendingLogins].
+#[derive(Serialize, Deserialize)]
 pub enum MinecraftAuthStep {
     /// Initialize the Minecraft Authorization Flow.
     Init(),
@@ -34,6 +81,9 @@ pub enum MinecraftAuthStep {
     /// The user must authorize the app within this time.
     /// If the user does not authorize the app within this time, the user code will expire.
     DeviceCode(TimeSensitiveData<DeviceCodeResponse>),
+    /// The another way to start the flow, the user login in browser and the browser redirect
+    /// the authorization code back to our loopback listener, so the user don't need to enter any code.
+    AuthorizationCode(AuthorizationCodeRequest),
     /// Wait for the user to authorize the app.
     MicrosoftAuth(TimeSensitiveData<MicrosoftAuthResponse>),
 
@@ -54,13 +104,76 @@ pub enum MinecraftAuthStep {
     ),
 }
 
-#[allow(dead_code)]
+/// What the server responded when a step failed, it is kept in [MinecraftAuthError] so that
+/// the frontend can show the reason to user and we can find out what happened from the log.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResponseDetails {
+    /// The HTTP status code, it is `None` when we didn't get any response.
+    pub status: Option<u16>,
+    /// The human-readable reason, like `error_description` of Microsoft or `Message` of Xbox.
+    pub description: Option<String>,
+    /// The raw body of the response.
+    pub body: String,
+}
+
+impl ResponseDetails {
+    pub(crate) async fn from_response(response: Response) -> Self {
+        let status = response.status().as_u16();
+        let body = response.text().await.unwrap_or_default();
+        let value = serde_json::from_str::<Value>(&body).unwrap_or_default();
+        let description = ["error_description", "errorMessage", "Message"]
+            .iter()
+            .find_map(|key| value[key].as_str())
+            .map(|x| x.to_string());
+
+        Self {
+            status: Some(status),
+            description,
+            body,
+        }
+    }
+
+    pub(crate) fn from_description(description: &str) -> Self {
+        Self {
+            description: Some(description.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<reqwest::Error> for ResponseDetails {
+    fn from(value: reqwest::Error) -> Self {
+        Self {
+            status: value.status().map(|x| x.as_u16()),
+            description: Some(value.to_string()),
+            body: String::new(),
+        }
+    }
+}
+
+impl Display for ResponseDetails {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.status, &self.description) {
+            (Some(status), Some(description)) => write!(f, "status code:{}, {}", status, description),
+            (Some(status), None) => write!(f, "status code:{}", status),
+            (None, Some(description)) => write!(f, "{}", description),
+            (None, None) => write!(f, "Unknown Error"),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum MinecraftAuthError {
     #[error("Your Minecraft Auth Flow call wrong step! Please check your code.")]
     InvalidState,
+    #[error("The login session is not found or expired, please login again.")]
+    SessionNotFound,
+    #[error("Failed to connect to the server. details:{0}")]
+    RequestError(ResponseDetails),
+    #[error("The server returned an unexpected response. details:{0}")]
+    InvalidResponse(ResponseDetails),
     #[error("Failed to get device code. details:{0}")]
-    GetDeviceCodeError(String),
+    GetDeviceCodeError(ResponseDetails),
     #[error("Failed to exchange device code. please try again. details:AuthorizationPending")]
     AuthorizationPending,
     #[error("Failed to exchange device code. details: AuthorizationDeclined")]
@@ -69,22 +182,80 @@ pub enum MinecraftAuthError {
     BadVerificationCode,
     #[error("Failed to exchange device code. details: ExpiredToken")]
     ExpiredToken,
+    #[error("Failed to exchange device code. please try again later. details: SlowDown")]
+    SlowDown,
+    #[error("Failed to get Microsoft token. details:{0}")]
+    MicrosoftTokenError(ResponseDetails),
+    #[error("Failed to get authorization code. details:{0}")]
+    AuthorizationCodeError(String),
+    #[error("The login page in browser didn't finish in time, please try again.")]
+    AuthorizationTimeout,
     #[error("Failed to refresh token code. details:{0}")]
-    RefreshMicrosoftTokenError(String),
+    RefreshMicrosoftTokenError(ResponseDetails),
     #[error("Failed to fetching Xbox Data. details:{0}")]
-    XboxAuthError(String),
+    XboxAuthError(ResponseDetails),
     #[error("The account doesn't have an Xbox account. Once they sign up for one (or login through minecraft.net to create one) then they can proceed with the login")]
     XboxAccountNotExist,
-    #[error("The account doesn't have a Minecraft account. Once they sign up for one (or login through minecraft.net to create one) then they can proceed with the login")]
+    #[error("The account is from a country where Xbox Live is not available or banned.")]
     XboxAccountCountryBan,
     #[error("The account needs adult verification on Xbox page")]
     XboxAccountNeedAdultVerification,
     #[error("The account is a child (under 18) and cannot proceed unless the account is added to a Family by an adult.")]
     AddToFamily,
-    #[error("Profile Not Found. details:{0}")]
-    ProfileNotFound(String),
-    #[error("Unknown Error. details:{0}")]
-    UnknownError(String),
+    #[error("This account is not a Microsoft account.")]
+    NotMicrosoftAccount,
+    #[error("The login imported from other launcher has expired or can't be renewed by us, please sign in again.")]
+    ImportedLoginExpired,
+    #[error(transparent)]
+    YggdrasilError(#[from] YggdrasilError),
+    #[error("The account owns Minecraft but has no profile yet, please create one on minecraft.net first. details:{0}")]
+    ProfileNotFound(ResponseDetails),
+    #[error("Failed to fetch data from Minecraft services. details:{0}")]
+    MinecraftServiceError(ResponseDetails),
+    #[error("The skin is invalid. details:{0}")]
+    InvalidSkin(String),
+    #[error("The name {0} is already taken.")]
+    NameDuplicate(String),
+    #[error("The name {0} is not allowed, a name should be 3-16 characters of a-z, A-Z, 0-9 and _ without bad words.")]
+    NameNotAllowed(String),
+    #[error("The name can only be changed once every 30 days, please try again later. details:{0}")]
+    NameChangeCooldown(ResponseDetails),
+}
+
+impl MinecraftAuthError {
+    /// The response of server which cause this error, if there is.
+    pub fn details(&self) -> Option<&ResponseDetails> {
+        match self {
+            MinecraftAuthError::RequestError(details)
+            | MinecraftAuthError::InvalidResponse(details)
+            | MinecraftAuthError::GetDeviceCodeError(details)
+            | MinecraftAuthError::MicrosoftTokenError(details)
+            | MinecraftAuthError::RefreshMicrosoftTokenError(details)
+            | MinecraftAuthError::XboxAuthError(details)
+            | MinecraftAuthError::ProfileNotFound(details)
+            | MinecraftAuthError::MinecraftServiceError(details)
+            | MinecraftAuthError::NameChangeCooldown(details) => Some(details),
+            _ => None,
+        }
+    }
+}
+
+/// Parse the body of a successful response, a body we can't understand becomes [MinecraftAuthError::InvalidResponse]
+/// instead of panic.
+pub(crate) async fn parse_json<T: DeserializeOwned>(response: Response) -> Result<T, MinecraftAuthError> {
+    let status = response.status().as_u16();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| MinecraftAuthError::RequestError(e.into()))?;
+
+    serde_json::from_str(&body).map_err(|e| {
+        MinecraftAuthError::InvalidResponse(ResponseDetails {
+            status: Some(status),
+            description: Some(e.to_string()),
+            body,
+        })
+    })
 }
 
 fn to_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
@@ -127,20 +298,139 @@ pub struct MicrosoftAuthResponse {
     pub refresh_token: String,
 }
 
+/// The response of Xbox Live and XSTS authenticate.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+struct XboxAuthResponse {
+    token: String,
+    display_claims: XboxDisplayClaims,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct XboxDisplayClaims {
+    xui: Vec<XboxUserClaims>,
+}
+
+/// `xid` and `gtg` are only in the response of `http://xboxlive.com` relying party.
+#[derive(Debug, Deserialize, Clone)]
+struct XboxUserClaims {
+    uhs: String,
+    xid: Option<String>,
+    gtg: Option<String>,
+}
+
+impl XboxAuthResponse {
+    fn user_hash(&self) -> Result<String, MinecraftAuthError> {
+        self.display_claims
+            .xui
+            .first()
+            .map(|x| x.uhs.clone())
+            .ok_or(MinecraftAuthError::InvalidResponse(ResponseDetails::from_description(
+                "There is no user hash in Xbox response",
+            )))
+    }
+
+    fn xbox_profile(&self) -> Option<XboxProfile> {
+        let claims = self.display_claims.xui.first()?;
+        Some(XboxProfile {
+            xuid: claims.xid.clone()?,
+            gamertag: claims.gtg.clone()?,
+        })
+    }
+}
+
+/// The profile for the account which doesn't own the game, the uuid is the Minecraft account id in token,
+/// and the name is the gamertag when it is a valid Minecraft name.
+fn demo_profile(token: &MinecraftAuthResponse, xbox: Option<&XboxProfile>) -> MinecraftProfile {
+    let name = xbox
+        .map(|x| x.gamertag.clone())
+        .filter(|x| is_valid_name(x))
+        .unwrap_or("Player".to_string());
+
+    MinecraftProfile {
+        id: token.username.replace('-', ""),
+        name,
+        skins: vec![],
+        capes: vec![],
+    }
+}
+
+/// The Xbox profile of Microsoft account, the game needs the XUID for chat reporting and Realms.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct XboxProfile {
+    pub xuid: String,
+    pub gamertag: String,
+}
+
+/// The data of authorization code grant with PKCE, see RFC 7636.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizationCodeRequest {
+    /// The url user should open in browser.
+    pub url: String,
+    pub redirect_uri: String,
+    pub state: String,
+    pub code_verifier: String,
+}
+
+/// Generate a random string which is valid as PKCE code verifier and OAuth state.
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .collect()
+}
+
+/// The code challenge of S256 method, which is `BASE64URL(SHA256(code_verifier))`.
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// The progress of [MinecraftAuthorizationFlow::login], each step is reported before it starts.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "step")]
+pub enum LoginProgress {
+    /// The device code is ready, the user should open `verification_uri` and enter `user_code`.
+    DeviceCode {
+        user_code: String,
+        verification_uri: String,
+        /// RFC 3339 time when the code is expired.
+        expire_in: String,
+    },
+    /// Waiting for the user to enter the code.
+    WaitingForUser,
+    XboxLive,
+    XboxSecurity,
+    MinecraftToken,
+    Profile,
+}
+
 pub struct MinecraftAuthorizationFlow {
     client: Client,
     client_id: String,
+    endpoints: AuthEndpoints,
     pub status: MinecraftAuthStep,
+    /// Got in [MinecraftAuthorizationFlow::xbox_security_auth], it is None when Xbox didn't give it to us.
+    pub xbox_profile: Option<XboxProfile>,
 }
 
 #[derive(Serialize, Deserialize,Debug,Clone, TimeSensitive)]
 pub struct MinecraftAuthResponse {
-    username: String,
-    access_token: String,
+    pub username: String,
+    pub access_token: String,
     #[serde(deserialize_with = "to_duration", serialize_with = "to_u64")]
     #[dur]
-    expires_in: Duration,
-    token_type: String,
+    pub expires_in: Duration,
+    pub token_type: String,
+}
+
+impl MinecraftAuthResponse {
+    /// The Minecraft access token is a JWT, and its payload carries the xuid of the Xbox account.
+    pub fn xuid(&self) -> Option<String> {
+        let payload = self.access_token.split('.').nth(1)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+        let value = serde_json::from_slice::<Value>(&payload).ok()?;
+        value["xuid"].as_str().map(|x| x.to_string())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -172,25 +462,98 @@ pub struct MinecraftProfile {
     pub capes: Vec<MinecraftCaps>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EntitlementItem {
+    pub name: String,
+    #[serde(default)]
+    pub signature: String,
+}
+
+/// The products the account owns, which is the response of `/entitlements/mcstore`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Entitlements {
+    #[serde(default)]
+    pub items: Vec<EntitlementItem>,
+}
+
+impl Entitlements {
+    fn has(&self, name: &str) -> bool {
+        self.items.iter().any(|x| x.name == name)
+    }
+
+    /// Whether the account bought Minecraft: Java Edition.
+    pub fn owns_java(&self) -> bool {
+        self.has("game_minecraft") || self.has("product_minecraft")
+    }
+
+    /// Whether the account can play Java Edition by Xbox Game Pass.
+    pub fn has_game_pass(&self) -> bool {
+        self.items.iter().any(|x| x.name.starts_with("product_game_pass"))
+    }
+
+    pub fn can_play(&self) -> bool {
+        self.owns_java() || self.has_game_pass()
+    }
+}
+
 impl MinecraftAuthorizationFlow {
     pub fn new(client_id: &str) -> Self {
         Self {
             client: Client::new(),
             client_id: client_id.to_string(),
+            endpoints: AuthEndpoints::default(),
             status: MinecraftAuthStep::Init(),
+            xbox_profile: None,
         }
     }
 
     pub fn from(client_id: &str, step: MinecraftAuthStep) -> Self {
         Self {
-            client: Client::new(),
-            client_id: client_id.to_string(),
             status: step,
+            ..Self::new(client_id)
+        }
+    }
+
+    pub fn with_endpoints(mut self, endpoints: AuthEndpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn endpoints(&self) -> &AuthEndpoints {
+        &self.endpoints
+    }
+
+    /// Create a new flow which has same client id and endpoints but start from [MinecraftAuthStep::Init].
+    pub fn fork(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            client_id: self.client_id.clone(),
+            endpoints: self.endpoints.clone(),
+            status: MinecraftAuthStep::Init(),
+            xbox_profile: None,
         }
     }
 
     pub fn reset(&mut self) {
         self.status = MinecraftAuthStep::Init();
+        self.xbox_profile = None;
+    }
+
+    /// Whether the data of current step is expired, so the flow can't go on.
+    /// A flow which is not started or waiting for browser redirect is never expired.
+    pub fn is_expired(&self) -> bool {
+        match &self.status {
+            MinecraftAuthStep::Init() | MinecraftAuthStep::AuthorizationCode(_) => false,
+            MinecraftAuthStep::DeviceCode(data) => !data.is_vaild(),
+            MinecraftAuthStep::MicrosoftAuth(data)
+            | MinecraftAuthStep::XboxLiveAuth(data, _)
+            | MinecraftAuthStep::XboxSecurityAuth(data, _, _) => !data.is_vaild(),
+            MinecraftAuthStep::MinecraftAuth(_, data) => !data.is_vaild(),
+        }
     }
 
     pub async fn generate_device_code(&mut self) -> Result<(), MinecraftAuthError> {
@@ -200,7 +563,7 @@ impl MinecraftAuthorizationFlow {
         ]);
         let response = self
             .client
-            .post(DEVICECODE_URL)
+            .post(&self.endpoints.device_code)
             .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
             .form(&params)
             .send()
@@ -209,15 +572,14 @@ impl MinecraftAuthorizationFlow {
         let data: DeviceCodeResponse = match response {
             Ok(response) => {
                 if response.status() == 200 {
-                    response.json().await.expect("this should be success!")
+                    parse_json(response).await?
                 } else {
-                    return Err(MinecraftAuthError::GetDeviceCodeError(format!(
-                        "Failed to get device code. status code:{}",
-                        response.status()
-                    )));
+                    return Err(MinecraftAuthError::GetDeviceCodeError(
+                        ResponseDetails::from_response(response).await,
+                    ));
                 }
             }
-            Err(e) => return Err(MinecraftAuthError::GetDeviceCodeError(e.to_string())),
+            Err(e) => return Err(MinecraftAuthError::GetDeviceCodeError(e.into())),
         };
 
         self.status = MinecraftAuthStep::DeviceCode(TimeSensitiveData::new(data));
@@ -236,38 +598,32 @@ impl MinecraftAuthorizationFlow {
             (String::from("device_code"), data.data.device_code.clone()),
         ]);
 
-        let request = self
+        let response = self
             .client
-            .post(TOKEN_URL)
+            .post(&self.endpoints.token)
             .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
-            .form(&params);
+            .form(&params)
+            .send()
+            .await;
 
-        let response = request.try_clone().expect("should can").send().await;
         let response: MicrosoftAuthResponse = match response {
             Ok(response) => {
                 if response.status() == 200 {
-                    response.json().await.expect("this should be success!")
+                    parse_json(response).await?
                 } else {
-                    return match response
-                        .json::<Value>()
-                        .await
-                        .expect("this should be success!")
-                        .get("error")
-                        .expect("this should be success!")
-                        .as_str()
-                        .expect("this should be success!")
-                    {
-                        "authorization_pending" => Err(MinecraftAuthError::AuthorizationPending),
-                        "authorization_declined" => Err(MinecraftAuthError::AuthorizationDeclined),
-                        "bad_verification_code" => Err(MinecraftAuthError::BadVerificationCode),
-                        "expired_token" => Err(MinecraftAuthError::ExpiredToken),
-                        _ => Err(MinecraftAuthError::UnknownError(
-                            "Unknown Error".to_string(),
-                        )),
+                    let details = ResponseDetails::from_response(response).await;
+                    let value = serde_json::from_str::<Value>(&details.body).unwrap_or_default();
+                    return match value["error"].as_str() {
+                        Some("authorization_pending") => Err(MinecraftAuthError::AuthorizationPending),
+                        Some("authorization_declined") => Err(MinecraftAuthError::AuthorizationDeclined),
+                        Some("bad_verification_code") => Err(MinecraftAuthError::BadVerificationCode),
+                        Some("expired_token") => Err(MinecraftAuthError::ExpiredToken),
+                        Some("slow_down") => Err(MinecraftAuthError::SlowDown),
+                        _ => Err(MinecraftAuthError::MicrosoftTokenError(details)),
                     };
                 }
             }
-            Err(e) => return Err(MinecraftAuthError::UnknownError(e.to_string())),
+            Err(e) => return Err(MinecraftAuthError::RequestError(e.into())),
         };
 
         self.status = MinecraftAuthStep::MicrosoftAuth(
@@ -277,6 +633,224 @@ impl MinecraftAuthorizationFlow {
         Ok(())
     }
 
+    /// Keep exchanging the device code until the user enter the code or the code is expired.
+    /// The server tells us how often we can ask by `interval`, and we should ask slower when it says `slow_down`.
+    pub async fn poll_device_code(&mut self) -> Result<(), MinecraftAuthError> {
+        let mut interval = match &self.status {
+            MinecraftAuthStep::DeviceCode(data) => data.data.interval,
+            _ => return Err(MinecraftAuthError::InvalidState),
+        };
+
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+
+            match &self.status {
+                MinecraftAuthStep::DeviceCode(data) if !data.is_vaild() => {
+                    return Err(MinecraftAuthError::ExpiredToken)
+                }
+                _ => {}
+            }
+
+            match self.exchange_device_code().await {
+                Err(MinecraftAuthError::AuthorizationPending) => {} // just wait next time
+                Err(MinecraftAuthError::SlowDown) => interval += 5, // see RFC 8628 section 3.5
+                result => return result,
+            }
+        }
+    }
+
+    /// Walk through the whole device code flow, `on_progress` is called when a step starts.
+    /// The flow is reset when it is failed, so it can be used to login again.
+    pub async fn login<F>(&mut self, mut on_progress: F) -> Result<Account, MinecraftAuthError>
+    where
+        F: FnMut(LoginProgress),
+    {
+        if let Err(e) = self.generate_device_code().await {
+            self.reset();
+            return Err(e);
+        }
+        if let MinecraftAuthStep::DeviceCode(data) = &self.status {
+            on_progress(LoginProgress::DeviceCode {
+                user_code: data.data.user_code.clone(),
+                verification_uri: data.data.verification_uri.clone(),
+                expire_in: (data.time + data.data.expires_in).to_rfc3339(),
+            });
+        }
+
+        self.wait_for_login(on_progress).await
+    }
+
+    /// The rest of [Self::login] after the device code is generated, which waits for the user and finishes the flow.
+    pub async fn wait_for_login<F>(&mut self, mut on_progress: F) -> Result<Account, MinecraftAuthError>
+    where
+        F: FnMut(LoginProgress),
+    {
+        let result = async {
+            on_progress(LoginProgress::WaitingForUser);
+            self.poll_device_code().await?;
+
+            on_progress(LoginProgress::XboxLive);
+            self.xbox_live_auth().await?;
+
+            on_progress(LoginProgress::XboxSecurity);
+            self.xbox_security_auth().await?;
+
+            on_progress(LoginProgress::MinecraftToken);
+            self.get_minecraft_token().await?;
+
+            on_progress(LoginProgress::Profile);
+            self.check_minecraft_profile().await
+        }
+        .await;
+
+        if result.is_err() {
+            self.reset();
+        }
+        result
+    }
+
+    /// Exchange the refresh token for a new Microsoft token, which is used when the stored token is expired.
+    /// The user don't need to do anything in this step.
+    pub async fn refresh_microsoft_token(&mut self, refresh_token: &str) -> Result<(), MinecraftAuthError> {
+        let params: HashMap<String, String> = HashMap::from([
+            (String::from("client_id"), self.client_id.clone()),
+            (String::from("scope"), String::from(SCOPE)),
+            (String::from("grant_type"), String::from(REFRESH_GRANT_TYPE)),
+            (String::from("refresh_token"), refresh_token.to_string()),
+        ]);
+
+        let response = self
+            .client
+            .post(&self.endpoints.token)
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .form(&params)
+            .send()
+            .await;
+
+        let response: MicrosoftAuthResponse = match response {
+            Ok(response) => {
+                if response.status() == 200 {
+                    parse_json(response).await?
+                } else {
+                    return Err(MinecraftAuthError::RefreshMicrosoftTokenError(
+                        ResponseDetails::from_response(response).await,
+                    ));
+                }
+            }
+            Err(e) => return Err(MinecraftAuthError::RequestError(e.into())),
+        };
+
+        self.status = MinecraftAuthStep::MicrosoftAuth(TimeSensitiveData::new(response));
+
+        Ok(())
+    }
+
+    /// Start the authorization code flow, return the loopback listener which will receive the redirect.
+    /// After this, open the url in [AuthorizationCodeRequest] by browser and call [Self::exchange_authorization_code].
+    pub async fn generate_authorization_url(&mut self) -> Result<TcpListener, MinecraftAuthError> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| MinecraftAuthError::AuthorizationCodeError(e.to_string()))?;
+        let port = listener
+            .local_addr()
+            .map_err(|e| MinecraftAuthError::AuthorizationCodeError(e.to_string()))?
+            .port();
+
+        let redirect_uri = format!("http://localhost:{port}");
+        let state = random_string(16);
+        let code_verifier = random_string(64);
+
+        let url = Url::parse_with_params(
+            &self.endpoints.authorize,
+            &[
+                ("client_id", self.client_id.as_str()),
+                ("response_type", "code"),
+                ("redirect_uri", redirect_uri.as_str()),
+                ("response_mode", "query"),
+                ("scope", SCOPE),
+                ("state", state.as_str()),
+                ("code_challenge", code_challenge(&code_verifier).as_str()),
+                ("code_challenge_method", "S256"),
+                ("prompt", "select_account"),
+            ],
+        )
+        .map_err(|e| MinecraftAuthError::AuthorizationCodeError(e.to_string()))?;
+
+        self.status = MinecraftAuthStep::AuthorizationCode(AuthorizationCodeRequest {
+            url: url.to_string(),
+            redirect_uri,
+            state,
+            code_verifier,
+        });
+
+        Ok(listener)
+    }
+
+    /// Wait for the browser redirect to the loopback listener, and exchange the authorization code for token.
+    pub async fn exchange_authorization_code(&mut self, listener: TcpListener) -> Result<(), MinecraftAuthError> {
+        let data = match &self.status {
+            MinecraftAuthStep::AuthorizationCode(data) => data,
+            _ => return Err(MinecraftAuthError::InvalidState),
+        };
+
+        let query = tokio::time::timeout(AUTHORIZATION_CODE_TIMEOUT, receive_redirect(&listener))
+            .await
+            .map_err(|_| MinecraftAuthError::AuthorizationTimeout)??;
+
+        if let Some(error) = query.get("error") {
+            return match error.as_str() {
+                "access_denied" => Err(MinecraftAuthError::AuthorizationDeclined),
+                _ => Err(MinecraftAuthError::AuthorizationCodeError(
+                    query.get("error_description").unwrap_or(error).to_string(),
+                )),
+            };
+        }
+
+        if query.get("state") != Some(&data.state) {
+            return Err(MinecraftAuthError::AuthorizationCodeError(
+                "The state is not matched".to_string(),
+            ));
+        }
+
+        let code = query
+            .get("code")
+            .ok_or(MinecraftAuthError::AuthorizationCodeError("No code received".to_string()))?;
+
+        let params: HashMap<String, String> = HashMap::from([
+            (String::from("client_id"), self.client_id.clone()),
+            (String::from("scope"), String::from(SCOPE)),
+            (String::from("grant_type"), String::from(AUTHORIZATION_CODE_GRANT_TYPE)),
+            (String::from("code"), code.to_string()),
+            (String::from("redirect_uri"), data.redirect_uri.clone()),
+            (String::from("code_verifier"), data.code_verifier.clone()),
+        ]);
+
+        let response = self
+            .client
+            .post(&self.endpoints.token)
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .form(&params)
+            .send()
+            .await;
+
+        let response: MicrosoftAuthResponse = match response {
+            Ok(response) => {
+                if response.status() == 200 {
+                    parse_json(response).await?
+                } else {
+                    return Err(MinecraftAuthError::MicrosoftTokenError(
+                        ResponseDetails::from_response(response).await,
+                    ));
+                }
+            }
+            Err(e) => return Err(MinecraftAuthError::RequestError(e.into())),
+        };
+
+        self.status = MinecraftAuthStep::MicrosoftAuth(TimeSensitiveData::new(response));
+
+        Ok(())
+    }
+
     pub async fn xbox_live_auth(&mut self) -> Result<(), MinecraftAuthError> {
         let data = match &self.status {
             MinecraftAuthStep::MicrosoftAuth(data) => data,
@@ -298,104 +872,90 @@ impl MinecraftAuthorizationFlow {
 
         let response = self
             .client
-            .post(XBOX_USER_AUTHENTICATE)
+            .post(&self.endpoints.xbox_user_authenticate)
             .json(&xbox_authenticate_json)
             .send()
             .await;
 
-        let res = match response {
+        let res: XboxAuthResponse = match response {
             Ok(response) => {
                 if response.status() == 200 {
-                    response
+                    parse_json(response).await?
                 } else {
-                    return Err(MinecraftAuthError::XboxAuthError(format!(
-                        "Failed to get Xbox Data. status code:{}",
-                        response.status()
-                    )));
+                    return Err(MinecraftAuthError::XboxAuthError(
+                        ResponseDetails::from_response(response).await,
+                    ));
                 }
             }
-            Err(e) => return Err(MinecraftAuthError::UnknownError(e.to_string())),
+            Err(e) => return Err(MinecraftAuthError::RequestError(e.into())),
         };
 
-        let token = res
-            .json::<Value>()
-            .await
-            .expect("this should be success!")
-            .get("Token")
-            .expect("this should be success!")
-            .as_str()
-            .expect("this should be success!")
-            .to_string();
-
-        self.status = MinecraftAuthStep::XboxLiveAuth(data.clone(), token);
+        self.status = MinecraftAuthStep::XboxLiveAuth(data.clone(), res.token);
 
         Ok(())
     }
 
     pub async fn xbox_security_auth(&mut self) -> Result<(), MinecraftAuthError> {
         let (data, token) = match &self.status {
-            MinecraftAuthStep::XboxLiveAuth(data, token) => (data, token),
+            MinecraftAuthStep::XboxLiveAuth(data, token) => (data.clone(), token.clone()),
             _ => return Err(MinecraftAuthError::InvalidState),
         };
 
+        let res = self.xsts_authorize(&token, "rp://api.minecraftservices.com/").await?;
+        let user_hash = res.user_hash()?;
+
+        // the token for Minecraft doesn't have the XUID and gamertag, so ask Xbox Live for them,
+        // the login can still go on without them.
+        self.xbox_profile = match self.xsts_authorize(&token, "http://xboxlive.com").await {
+            Ok(res) => res.xbox_profile(),
+            Err(e) => {
+                log::warn!("Failed to get the Xbox profile: {}", e);
+                None
+            }
+        };
+
+        self.status = MinecraftAuthStep::XboxSecurityAuth(data, res.token, user_hash);
+
+        Ok(())
+    }
+
+    /// Exchange the Xbox Live token for the XSTS token of `relying_party`.
+    async fn xsts_authorize(&self, token: &str, relying_party: &str) -> Result<XboxAuthResponse, MinecraftAuthError> {
         let xbox_authenticate_json = json!({
             "Properties": {
                 "SandboxId": "RETAIL",
                 "UserTokens": [token],
             },
-            "RelyingParty": "rp://api.minecraftservices.com/",
+            "RelyingParty": relying_party,
             "TokenType": "JWT"
         });
         let response = self
             .client
-            .post(XBOX_XSTS_AUTHORIZE)
+            .post(&self.endpoints.xbox_xsts_authorize)
             .json(&xbox_authenticate_json)
             .send()
             .await;
 
-        let res = match response {
+        match response {
             Ok(response) => {
                 if response.status() == 200 {
-                    response
+                    parse_json(response).await
                 } else {
-                    let value = response
-                        .json::<Value>()
-                        .await
-                        .expect("this should be success!");
-                    return match value["XErr"].as_u64().expect("should be x") {
-                        2148916233 => Err(MinecraftAuthError::XboxAccountNotExist),
-                        2148916235 => Err(MinecraftAuthError::XboxAccountCountryBan),
-                        2148916236 | 2148916237 => {
+                    let details = ResponseDetails::from_response(response).await;
+                    let value = serde_json::from_str::<Value>(&details.body).unwrap_or_default();
+                    match value["XErr"].as_u64() {
+                        Some(2148916233) => Err(MinecraftAuthError::XboxAccountNotExist),
+                        Some(2148916235) => Err(MinecraftAuthError::XboxAccountCountryBan),
+                        Some(2148916236) | Some(2148916237) => {
                             Err(MinecraftAuthError::XboxAccountNeedAdultVerification)
                         }
-                        2148916238 => Err(MinecraftAuthError::AddToFamily),
-                        _ => Err(MinecraftAuthError::XboxAuthError(
-                            "Unknown Error".to_string(),
-                        )),
-                    };
+                        Some(2148916238) => Err(MinecraftAuthError::AddToFamily),
+                        _ => Err(MinecraftAuthError::XboxAuthError(details)),
+                    }
                 }
             }
-            Err(e) => return Err(MinecraftAuthError::UnknownError(e.to_string())),
-        };
-
-        let value = res
-            .json::<Value>()
-            .await
-            .or(Err(MinecraftAuthError::UnknownError(
-                "Failed to parse response".to_string(),
-            )))?;
-        let token = value["Token"]
-            .as_str()
-            .expect("this should be success!")
-            .to_string();
-        let user_hash = value["DisplayClaims"]["xui"][0]["uhs"]
-            .as_str()
-            .expect("this should be success!")
-            .to_string();
-
-        self.status = MinecraftAuthStep::XboxSecurityAuth(data.clone(), token, user_hash);
-
-        Ok(())
+            Err(e) => Err(MinecraftAuthError::RequestError(e.into())),
+        }
     }
 
     pub async fn get_minecraft_token(
@@ -408,7 +968,7 @@ impl MinecraftAuthorizationFlow {
 
         let response = self
             .client
-            .post(MINECRAFT_LOGIN_WITH_XBOX)
+            .post(&self.endpoints.minecraft_login_with_xbox)
             .header("Content-Type", "application/json")
             .json(&json!({
                 "identityToken": format!("XBL3.0 x={};{}",uhs,token)
@@ -419,18 +979,17 @@ impl MinecraftAuthorizationFlow {
         let res: MinecraftAuthResponse = match response {
             Ok(response) => {
                 if response.status() == 200 {
-                    response.json().await.expect("this should be success!")
+                    parse_json(response).await?
                 } else {
-                    return Err(MinecraftAuthError::XboxAuthError(format!(
-                        "Failed to get Xbox Data. status code:{}",
-                        response.status()
-                    )));
+                    return Err(MinecraftAuthError::XboxAuthError(
+                        ResponseDetails::from_response(response).await,
+                    ));
                 }
             }
-            Err(e) => return Err(MinecraftAuthError::UnknownError(e.to_string())),
+            Err(e) => return Err(MinecraftAuthError::RequestError(e.into())),
         };
 
-        let minecraft_auth =TimeSensitiveData::new(res);
+        let minecraft_auth = TimeSensitiveData::new(res);
         self.status = MinecraftAuthStep::MinecraftAuth(data.clone(), minecraft_auth.clone());
 
         Ok(minecraft_auth.clone())
@@ -442,9 +1001,12 @@ impl MinecraftAuthorizationFlow {
             _ => return Err(MinecraftAuthError::InvalidState),
         };
 
+        // the entitlements are only needed to know why there is no profile, so the failure of it is not fatal
+        let entitlements = self.check_entitlements(&profile.data.access_token).await;
+
         let response = self
             .client
-            .get(MINECRAFT_PROFILE)
+            .get(&self.endpoints.minecraft_profile)
             .bearer_auth(profile.data.access_token.clone())
             .send()
             .await;
@@ -452,53 +1014,605 @@ impl MinecraftAuthorizationFlow {
         let profile_data = match response {
             Ok(response) => {
                 if response.status() == 200 {
-                    response
-                        .json::<MinecraftProfile>()
-                        .await
-                        .expect("this should be success!")
+                    parse_json::<MinecraftProfile>(response).await?
+                } else if response.status() == 404 {
+                    // Game Pass accounts and the accounts which just bought the game may not have profile yet,
+                    // and the accounts without the game never have one, they can only play the demo.
+                    match entitlements {
+                        Ok(ref entitlements) if !entitlements.can_play() => {
+                            demo_profile(&profile.data, self.xbox_profile.as_ref())
+                        }
+                        Ok(_) => {
+                            return Err(MinecraftAuthError::ProfileNotFound(
+                                ResponseDetails::from_response(response).await,
+                            ))
+                        }
+                        // we can't tell whether it is a demo account
+                        Err(e) => return Err(e),
+                    }
                 } else {
-                    return Err(MinecraftAuthError::ProfileNotFound(
-                        response.text().await.expect("this should be success!"),
+                    return Err(MinecraftAuthError::MinecraftServiceError(
+                        ResponseDetails::from_response(response).await,
                     ));
                 }
             }
-            Err(e) => return Err(MinecraftAuthError::UnknownError(e.to_string())),
+            Err(e) => return Err(MinecraftAuthError::RequestError(e.into())),
         };
 
+        let xbox = self.xbox_profile.clone();
         self.reset();
 
         Ok(Account{
             profile: profile_data,
-            msa: data,
+            account_type: AccountType::Microsoft,
+            msa: Some(data),
+            minecraft: Some(profile),
+            yggdrasil: None,
+            entitlements: entitlements
+                .map_err(|e| log::warn!("Failed to check the entitlements: {}", e))
+                .ok(),
+            needs_relogin: false,
+            xbox,
+            meta: AccountMeta::default(),
         })
     }
-}
 
-pub type AuthFlow = RwLock<MinecraftAuthorizationFlow>;
+    /// Get the products the account owns by Minecraft access token.
+    pub async fn check_entitlements(&self, access_token: &str) -> Result<Entitlements, MinecraftAuthError> {
+        let response = self
+            .client
+            .get(&self.endpoints.minecraft_entitlements)
+            .bearer_auth(access_token)
+            .send()
+            .await;
 
-#[derive(Serialize,Deserialize,Clone,Debug)]
-pub struct Account{
-    pub profile:MinecraftProfile,
-    pub msa:TimeSensitiveData<MicrosoftAuthResponse>
+        match response {
+            Ok(response) => {
+                if response.status() == 200 {
+                    parse_json(response).await
+                } else {
+                    Err(MinecraftAuthError::MinecraftServiceError(
+                        ResponseDetails::from_response(response).await,
+                    ))
+                }
+            }
+            Err(e) => Err(MinecraftAuthError::RequestError(e.into())),
+        }
+    }
+
+    /// Walk through Xbox Live, XSTS and Minecraft login with a Microsoft token we already have,
+    /// so an account which logged in before can get a new Minecraft token without the device code.
+    /// The Xbox profile is returned too, see [MinecraftAuthorizationFlow::xbox_profile].
+    pub async fn login_with_microsoft_token(
+        &mut self,
+        msa: TimeSensitiveData<MicrosoftAuthResponse>,
+    ) -> Result<(TimeSensitiveData<MinecraftAuthResponse>, Option<XboxProfile>), MinecraftAuthError> {
+        self.status = MinecraftAuthStep::MicrosoftAuth(msa);
+        self.xbox_live_auth().await?;
+        self.xbox_security_auth().await?;
+        let token = self.get_minecraft_token().await?;
+        let xbox = self.xbox_profile.take();
+        self.reset();
+        Ok((token, xbox))
+    }
 }
 
-#[derive(Serialize,Deserialize,Clone,Debug,Save,Load,Storage,Default)]
-#[serde(transparent)]
-#[save_path(ACCOUNTS_DATA)]
-pub struct AccountList(pub Vec<Account>);
+/// Accept connections on the loopback listener until the browser redirect to it with the query we need.
+/// The query is returned as map, it should contain `code` and `state`, or `error` when the user declined.
+async fn receive_redirect(listener: &TcpListener) -> Result<HashMap<String, String>, MinecraftAuthError> {
+    loop {
+        let (mut stream, _) = listener
+            .accept()
+            .await
+            .map_err(|e| MinecraftAuthError::AuthorizationCodeError(e.to_string()))?;
 
-impl AccountList {
-    pub fn add(&mut self,account:Account){
-        let data = self.0.iter().find(
-            |x| x.profile.id == account.profile.id
-        );
-        
-        if data.is_none(){
-            self.0.push(account);
+        // we only need the request line, so stop when the header is end.
+        let mut request = Vec::new();
+        let read = tokio::time::timeout(REDIRECT_READ_TIMEOUT, async {
+            let mut buffer = [0u8; 1024];
+            while let Ok(size) = stream.read(&mut buffer).await {
+                request.extend_from_slice(&buffer[..size]);
+                if size == 0 || request.windows(4).any(|x| x == b"\r\n\r\n") || request.len() > 16384 {
+                    break;
+                }
+            }
+        })
+        .await;
+
+        // the connection which doesn't send its request is dropped, so it won't block the redirect
+        if read.is_err() {
+            continue;
         }
-    }
-    
-    pub fn find(&mut self,id:&str) -> Option<&mut Account>{
+
+        let request = String::from_utf8_lossy(&request);
+        let path = request
+            .lines()
+            .next()
+            .and_then(|x| x.split(' ').nth(1))
+            .unwrap_or("/");
+        let query: HashMap<String, String> = match Url::parse(&format!("http://localhost{path}")) {
+            Ok(url) => url.query_pairs().into_owned().collect(),
+            Err(_) => HashMap::new(),
+        };
+
+        // browser may request other things like favicon.ico, ignore them.
+        if !query.contains_key("code") && !query.contains_key("error") {
+            let _ = stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await;
+            continue;
+        }
+
+        let body = "<html><body>NoLauncher has received the login result, you can close this page now.</body></html>";
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        let _ = stream.shutdown().await;
+
+        return Ok(query);
+    }
+}
+
+/// The login sessions in progress, each session has its own flow, so multiple accounts can login at the same time.
+pub struct LoginSessions {
+    /// The flow which new sessions are forked from.
+    flow: MinecraftAuthorizationFlow,
+    sessions: RwLock<HashMap<String, Arc<Mutex<MinecraftAuthorizationFlow>>>>,
+}
+
+impl LoginSessions {
+    pub fn new(flow: MinecraftAuthorizationFlow) -> Self {
+        Self {
+            flow,
+            sessions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Start a new session, the expired sessions are removed at the same time.
+    pub async fn create(&self) -> (String, Arc<Mutex<MinecraftAuthorizationFlow>>) {
+        self.remove_expired().await;
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let flow = Arc::new(Mutex::new(self.flow.fork()));
+        self.sessions.write().await.insert(id.clone(), flow.clone());
+        (id, flow)
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Arc<Mutex<MinecraftAuthorizationFlow>>, MinecraftAuthError> {
+        let flow = self
+            .sessions
+            .read()
+            .await
+            .get(id)
+            .cloned()
+            .ok_or(MinecraftAuthError::SessionNotFound)?;
+
+        // the session which is in use is never expired.
+        let expired = flow.try_lock().map(|x| x.is_expired()).unwrap_or(false);
+        if expired {
+            self.remove(id).await;
+            return Err(MinecraftAuthError::SessionNotFound);
+        }
+
+        Ok(flow)
+    }
+
+    /// Bring back the session saved before restart with the same id, see [PendingLogins].
+    pub async fn resume(&self, id: &str, status: MinecraftAuthStep) -> Arc<Mutex<MinecraftAuthorizationFlow>> {
+        let mut flow = self.flow.fork();
+        flow.status = status;
+
+        let flow = Arc::new(Mutex::new(flow));
+        self.sessions.write().await.insert(id.to_string(), flow.clone());
+        flow
+    }
+
+    pub async fn remove(&self, id: &str) {
+        self.sessions.write().await.remove(id);
+    }
+
+    async fn remove_expired(&self) {
+        self.sessions
+            .write()
+            .await
+            .retain(|_, flow| !flow.try_lock().map(|x| x.is_expired()).unwrap_or(false));
+    }
+}
+
+/// The login sessions waiting for the user, keyed by the session id. They are saved in the cache folder,
+/// so closing the app doesn't throw the device code away. Only the device code step is saved,
+/// the later steps have the tokens in it and finish in seconds.
+#[derive(Serialize, Deserialize, Default, Save, Load, Storage)]
+#[serde(transparent)]
+#[save_path(PENDING_LOGIN)]
+pub struct PendingLogins(pub HashMap<String, MinecraftAuthStep>);
+
+impl PendingLogins {
+    /// Drop the steps which can't be resumed, like the expired device code.
+    pub fn retain_valid(&mut self) {
+        self.0
+            .retain(|_, step| matches!(step, MinecraftAuthStep::DeviceCode(data) if data.is_vaild()));
+    }
+}
+
+pub type SafePendingLogins = Mutex<PendingLogins>;
+
+/// The kind of account, old accounts.json doesn't have this field, so it is default to Microsoft.
+#[derive(Serialize,Deserialize,Clone,Debug,Default,PartialEq)]
+pub enum AccountType {
+    #[default]
+    Microsoft,
+    /// An account without any authentication, which can only play on offline-mode servers.
+    Offline,
+    /// An account on third-party Yggdrasil server, which is used with authlib-injector.
+    Yggdrasil,
+}
+
+impl AccountType {
+    /// The value of `${user_type}` in launch arguments.
+    pub fn user_type(&self) -> &'static str {
+        match self {
+            AccountType::Microsoft => "msa",
+            AccountType::Offline => "legacy",
+            AccountType::Yggdrasil => "mojang",
+        }
+    }
+}
+
+#[derive(Serialize,Deserialize,Clone,Debug)]
+pub struct Account{
+    pub profile:MinecraftProfile,
+    #[serde(default)]
+    pub account_type:AccountType,
+    /// Only Microsoft accounts have this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub msa:Option<TimeSensitiveData<MicrosoftAuthResponse>>,
+    /// The latest Minecraft token, reused until it expires.
+    #[serde(default)]
+    pub minecraft:Option<TimeSensitiveData<MinecraftAuthResponse>>,
+    /// Only Yggdrasil accounts have this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub yggdrasil:Option<YggdrasilSession>,
+    /// The products the Microsoft account owns when it logged in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entitlements:Option<Entitlements>,
+    /// Set by the health check when the token can't be refreshed anymore.
+    #[serde(default)]
+    pub needs_relogin:bool,
+    /// The gamertag and XUID of Microsoft account, None for other accounts or the Xbox profile can't be fetched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xbox:Option<XboxProfile>,
+    #[serde(default)]
+    pub meta:AccountMeta,
+}
+
+/// The things user set for the account, they are kept when the account logs in again.
+#[derive(Serialize,Deserialize,Clone,Debug,Default,PartialEq)]
+pub struct AccountMeta{
+    /// Shown instead of the profile name when it is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nickname:Option<String>,
+    /// The position in account list, the smaller one is shown first.
+    #[serde(default)]
+    pub order:u32,
+    /// RFC 3339 time when the account launched the game last time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used:Option<String>,
+}
+
+/// The information about player which the game needs to launch.
+#[derive(Debug,Clone)]
+pub struct LaunchAuth {
+    pub name: String,
+    pub uuid: String,
+    pub access_token: String,
+    pub xuid: String,
+    pub user_type: &'static str,
+    /// Launch the game in demo mode, see [Account::is_demo].
+    pub demo: bool,
+    /// The API root of Yggdrasil server, authlib-injector is needed when this is not None.
+    pub yggdrasil_api: Option<String>,
+}
+
+/// Generate the uuid of offline player, which is same as the `UUID.nameUUIDFromBytes`
+/// used by Minecraft server, so the player will have same uuid on offline-mode servers.
+pub fn offline_uuid(name: &str) -> uuid::Uuid {
+    let hash: [u8; 16] = Md5::digest(format!("OfflinePlayer:{name}").as_bytes()).into();
+    uuid::Builder::from_md5_bytes(hash).into_uuid()
+}
+
+impl Account {
+    pub fn offline(name: &str) -> Self {
+        Self {
+            profile: MinecraftProfile {
+                id: offline_uuid(name).simple().to_string(),
+                name: name.to_string(),
+                skins: vec![],
+                capes: vec![],
+            },
+            account_type: AccountType::Offline,
+            msa: None,
+            minecraft: None,
+            yggdrasil: None,
+            entitlements: None,
+            needs_relogin: false,
+            xbox: None,
+            meta: AccountMeta::default(),
+        }
+    }
+
+    /// The Microsoft account which only has the Minecraft token, like the one imported from other launcher.
+    pub fn microsoft(profile: MinecraftProfile, minecraft: TimeSensitiveData<MinecraftAuthResponse>) -> Self {
+        Self {
+            profile,
+            account_type: AccountType::Microsoft,
+            msa: None,
+            minecraft: Some(minecraft),
+            yggdrasil: None,
+            entitlements: None,
+            needs_relogin: false,
+            xbox: None,
+            meta: AccountMeta::default(),
+        }
+    }
+
+    pub fn yggdrasil(session: YggdrasilSession, profile: YggdrasilProfile) -> Self {
+        Self {
+            profile: MinecraftProfile {
+                id: profile.id,
+                name: profile.name,
+                skins: vec![],
+                capes: vec![],
+            },
+            account_type: AccountType::Yggdrasil,
+            msa: None,
+            minecraft: None,
+            yggdrasil: Some(session),
+            entitlements: None,
+            needs_relogin: false,
+            xbox: None,
+            meta: AccountMeta::default(),
+        }
+    }
+
+    /// Get everything the game needs to know about the player, the token will be refreshed if needed.
+    /// The `flow` is only used for its client id and endpoints.
+    pub async fn launch_auth(&mut self, flow: &MinecraftAuthorizationFlow) -> Result<LaunchAuth, MinecraftAuthError> {
+        let (access_token, xuid) = match self.account_type {
+            AccountType::Microsoft => {
+                let token = self.minecraft_token(flow).await?;
+                let xuid = self
+                    .xbox
+                    .as_ref()
+                    .map(|x| x.xuid.clone())
+                    .or_else(|| token.xuid())
+                    .unwrap_or_default();
+                (token.access_token, xuid)
+            }
+            // offline-mode servers don't check token, but the game need one.
+            AccountType::Offline => ("0".to_string(), "0".to_string()),
+            AccountType::Yggdrasil => (self.yggdrasil_token().await?, "0".to_string()),
+        };
+
+        Ok(LaunchAuth {
+            name: self.profile.name.clone(),
+            uuid: self.profile.id.clone(),
+            access_token,
+            xuid,
+            user_type: self.account_type.user_type(),
+            demo: self.is_demo(),
+            yggdrasil_api: self.yggdrasil.as_ref().map(|x| x.api_root.clone()),
+        })
+    }
+
+    /// Get the access token of Yggdrasil account, it is refreshed when the server says it is invalid.
+    pub async fn yggdrasil_token(&mut self) -> Result<String, MinecraftAuthError> {
+        let session = self.yggdrasil.as_mut().ok_or(YggdrasilError::InvalidToken)?;
+        let server = YggdrasilServer::new(&session.api_root);
+        if !server.validate(session).await? {
+            let response = server.refresh(session, None).await?;
+            session.access_token = response.access_token;
+        }
+        Ok(session.access_token.clone())
+    }
+
+    fn msa(&self) -> Result<&TimeSensitiveData<MicrosoftAuthResponse>, MinecraftAuthError> {
+        match (&self.msa, &self.account_type) {
+            (Some(msa), _) => Ok(msa),
+            // the imported account has no Microsoft token we can use
+            (None, AccountType::Microsoft) => Err(MinecraftAuthError::ImportedLoginExpired),
+            (None, _) => Err(MinecraftAuthError::NotMicrosoftAccount),
+        }
+    }
+
+    /// Get a Minecraft token which can be passed to the game, a new one is requested
+    /// through Xbox Live when the stored one is missing or expired, and the Microsoft
+    /// token is refreshed first if it is expired too.
+    pub async fn minecraft_token(&mut self, flow: &MinecraftAuthorizationFlow) -> Result<MinecraftAuthResponse, MinecraftAuthError> {
+        if let Some(token) = &self.minecraft {
+            if token.is_vaild() {
+                return Ok(token.data.clone());
+            }
+        }
+
+        let msa = self.msa()?.clone();
+        if !msa.is_vaild() {
+            self.refresh(flow).await?;
+        } else {
+            let (token, xbox) = flow.fork().login_with_microsoft_token(msa).await?;
+            self.minecraft = Some(token);
+            self.update_xbox(xbox);
+        }
+
+        self.minecraft
+            .as_ref()
+            .map(|x| x.data.clone())
+            .ok_or(MinecraftAuthError::InvalidState)
+    }
+
+    /// Refresh the Microsoft token by the stored refresh token, then login to Minecraft again.
+    /// Remember to save the [AccountList] after this, the refresh token is rotated.
+    pub async fn refresh(&mut self, flow: &MinecraftAuthorizationFlow) -> Result<(), MinecraftAuthError> {
+        let mut flow = flow.fork();
+        flow.refresh_microsoft_token(&self.msa()?.data.refresh_token).await?;
+
+        let msa = match &flow.status {
+            MinecraftAuthStep::MicrosoftAuth(data) => data.clone(),
+            _ => return Err(MinecraftAuthError::InvalidState),
+        };
+        let (token, xbox) = flow.login_with_microsoft_token(msa.clone()).await?;
+
+        self.msa = Some(msa);
+        self.minecraft = Some(token);
+        self.update_xbox(xbox);
+        Ok(())
+    }
+
+    /// Whether the Microsoft account doesn't own Minecraft: Java Edition, which can only play the demo.
+    /// The accounts logged in before the entitlements were saved are not.
+    pub fn is_demo(&self) -> bool {
+        self.account_type == AccountType::Microsoft
+            && self.entitlements.as_ref().is_some_and(|x| !x.can_play())
+    }
+
+    /// Set the nickname, the empty one removes it.
+    pub fn set_nickname(&mut self, nickname: Option<String>) {
+        self.meta.nickname = nickname
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty());
+    }
+
+    /// Record the account is used to launch now.
+    pub fn mark_used(&mut self) {
+        self.meta.last_used = Some(chrono::Local::now().to_rfc3339());
+    }
+
+    /// Copy the tokens from `refreshed`, which is a clone of this account refreshed without holding the lock
+    /// of [AccountList]. The other fields may be changed by user during the refresh, so they are kept.
+    pub fn update_tokens(&mut self, refreshed: Account) {
+        self.msa = refreshed.msa;
+        self.minecraft = refreshed.minecraft;
+        self.yggdrasil = refreshed.yggdrasil;
+        self.xbox = refreshed.xbox;
+    }
+
+    /// Keep the stored Xbox profile when Xbox Live failed to give us a new one this time.
+    fn update_xbox(&mut self, xbox: Option<XboxProfile>) {
+        if xbox.is_some() {
+            self.xbox = xbox;
+        }
+    }
+}
+
+/// The accounts with Microsoft refresh tokens in it, it is sealed by the key in `accounts.key` beside it
+/// when `encrypt_accounts` is on in config, see [AccountList::set_encryption].
+#[derive(Serialize,Deserialize,Clone,Debug,Storage,Default)]
+#[serde(transparent)]
+#[save_path(ACCOUNTS_DATA)]
+pub struct AccountList(pub Vec<Account>, #[serde(skip)] StoreMode);
+
+/// How [AccountList] is saved, it is not a part of the file.
+#[derive(Clone, Debug, Default)]
+struct StoreMode {
+    encrypt: bool,
+    /// The file failed to load, so the empty list in memory must not overwrite it.
+    read_only: bool,
+}
+
+fn key_path(path: &Path) -> PathBuf {
+    path.with_extension("key")
+}
+
+fn read_or_create_key(key_path: &Path) -> Result<Key> {
+    if key_path.exists() {
+        crypto::read_key(key_path)
+    } else {
+        crypto::create_key(key_path)
+    }
+}
+
+impl Save for AccountList {
+    fn save(&self, path: &Path) -> Result<()> {
+        self.ensure_writable()?;
+        let key = if self.1.encrypt {
+            Some(read_or_create_key(&key_path(path))?)
+        } else {
+            None
+        };
+        self.write(path, key.as_ref())
+    }
+}
+
+impl Load<'_> for AccountList {
+    /// Both the sealed file and the plain JSON are accepted, so the old file is still loaded.
+    fn load(path: &Path) -> Result<Box<Self>> {
+        let mut content = std::fs::read(path)?;
+        if crypto::is_sealed(&content) {
+            content = crypto::open(&crypto::read_key(&key_path(path))?, &content)?;
+        }
+        Ok(Box::new(serde_json::from_slice(&content)?))
+    }
+}
+
+impl AccountList {
+    /// Add the account to the end of list, nothing is changed when there is one with same profile.
+    pub fn add(&mut self,mut account:Account){
+        if self.get(&account.profile.id).is_some() {
+            return;
+        }
+        account.meta.order = self.0.iter().map(|x| x.meta.order + 1).max().unwrap_or(0);
+        self.0.push(account);
+    }
+
+    /// Add the account which has logged in again, it replaces the one with same profile, so the new tokens
+    /// are used and `needs_relogin` is cleared. The [AccountMeta] of the replaced one is kept.
+    pub fn relogin(&mut self,mut account:Account){
+        match self.find(&account.profile.id) {
+            Some(data) => {
+                account.meta = data.meta.clone();
+                *data = account;
+            }
+            None => self.add(account),
+        }
+    }
+
+    /// The accounts in the order of [AccountMeta::order].
+    pub fn sorted(&self) -> Vec<&Account> {
+        let mut accounts: Vec<&Account> = self.0.iter().collect();
+        accounts.sort_by_key(|x| x.meta.order);
+        accounts
+    }
+
+    /// Move the accounts of `ids` to the front in that order, the others keep their order after them.
+    pub fn reorder(&mut self, ids: &[String]) -> Result<()> {
+        for id in ids {
+            anyhow::ensure!(self.0.iter().any(|x| &x.profile.id == id), "no user found: {}", id);
+        }
+
+        let rest = self
+            .sorted()
+            .into_iter()
+            .map(|x| x.profile.id.clone())
+            .filter(|x| !ids.contains(x))
+            .collect::<Vec<String>>();
+
+        for (order, id) in ids.iter().chain(rest.iter()).enumerate() {
+            if let Some(account) = self.find(id) {
+                account.meta.order = order as u32;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Same as [AccountList::find], but only the read lock is needed.
+    pub fn get(&self, id:&str) -> Option<&Account>{
+        self.0.iter().find(|x| x.profile.id == id)
+    }
+
+    pub fn find(&mut self,id:&str) -> Option<&mut Account>{
         for i in self.0.iter_mut() {
             if i.profile.id == id{
                 return Some(i);
@@ -511,8 +1625,732 @@ impl AccountList {
     pub fn remove(&mut self, id:&str){
         self.0.retain(|x| x.profile.id != id)
     }
-    
+
+    fn write(&self, path: &Path, key: Option<&Key>) -> Result<()> {
+        let mut content = serde_json::to_vec(self)?;
+        if let Some(key) = key {
+            content = crypto::seal(key, &content)?;
+        }
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    fn ensure_writable(&self) -> Result<()> {
+        anyhow::ensure!(
+            !self.1.read_only,
+            "The accounts file failed to load, so it is not changed until the app is restarted"
+        );
+        Ok(())
+    }
+
+    /// Create or remove the key of the file at `path`, and rewrite the file when it is in the other format,
+    /// so the plain file written before is migrated. The file should be loaded into this list first.
+    pub fn set_encryption(&mut self, path: &Path, enable: bool) -> Result<()> {
+        self.ensure_writable()?;
+        let key_path = key_path(path);
+        let sealed = std::fs::read(path).ok().map(|x| crypto::is_sealed(&x));
+
+        if enable {
+            let key = read_or_create_key(&key_path)?;
+            if sealed == Some(false) {
+                self.write(path, Some(&key))?;
+            }
+        } else if key_path.exists() {
+            if sealed == Some(true) {
+                self.write(path, None)?;
+            }
+            std::fs::remove_file(&key_path)?;
+        }
+
+        self.1.encrypt = enable;
+        Ok(())
+    }
+
+    pub fn set_encryption_by_app(&mut self, app: &AppHandle, enable: bool) -> Result<()> {
+        self.set_encryption(&ACCOUNTS_DATA.to_path(app)?, enable)
+    }
+
+    /// Load the list at `path` when app starts, and migrate the file to the storage mode in config.
+    /// When the file failed to load, the list is empty and read only, so the file is never overwritten.
+    pub fn init(path: &Path, encrypt: bool) -> Self {
+        let mut list = match Self::load(path) {
+            Ok(list) => *list,
+            // there is nothing to lose when the file is not created yet
+            Err(_) if !path.exists() => Self::default(),
+            Err(e) => {
+                log::error!("Failed to load the accounts: {}", e);
+                return Self(Vec::new(), StoreMode { encrypt, read_only: true });
+            }
+        };
+
+        // the file is saved in the mode of config even if the migration failed
+        list.1.encrypt = encrypt;
+        if let Err(e) = list.set_encryption(path, encrypt) {
+            log::error!("Failed to change the encryption of accounts: {}", e);
+        }
+        list
+    }
+
+    pub fn init_by_app(app: &AppHandle, encrypt: bool) -> Self {
+        match ACCOUNTS_DATA.to_path(app) {
+            Ok(path) => Self::init(&path, encrypt),
+            Err(e) => {
+                log::error!("Failed to find the accounts file: {}", e);
+                Self(Vec::new(), StoreMode { encrypt, read_only: true })
+            }
+        }
+    }
 }
 
 pub type SafeAccountList = RwLock<AccountList>;
 
+
+#[cfg(test)]
+pub(crate) mod test{
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use crate::utils::minecraft::auth::{code_challenge, offline_uuid, Account, AccountList, AccountType, AuthEndpoints, LoginProgress, LoginSessions, PendingLogins, MinecraftAuthError, MinecraftAuthStep, MinecraftAuthorizationFlow};
+    use crate::utils::data::TimeSensitiveData;
+    use crate::utils::config::{Load, Save};
+    use crate::utils::crypto;
+
+    /// A tiny http server for testing, which answer the request by the path with (path, status, body).
+    pub(crate) async fn mock_server(routes: Vec<(&'static str, u16, String)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+
+                // read the header, then the body by content-length
+                let header_end = loop {
+                    let size = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..size]);
+                    if let Some(pos) = request.windows(4).position(|x| x == b"\r\n\r\n") {
+                        break pos + 4;
+                    }
+                };
+                let header = String::from_utf8_lossy(&request[..header_end]).to_lowercase();
+                let length = header
+                    .lines()
+                    .find_map(|x| x.strip_prefix("content-length:"))
+                    .map(|x| x.trim().parse::<usize>().unwrap())
+                    .unwrap_or(0);
+                while request.len() < header_end + length {
+                    let size = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..size]);
+                }
+
+                let path = header.split(' ').nth(1).unwrap().to_string();
+                let (status, body) = routes
+                    .iter()
+                    .find(|(route, _, _)| path.starts_with(route))
+                    .map(|(_, status, body)| (*status, body.clone()))
+                    .unwrap_or((404, String::new()));
+
+                let response = format!(
+                    "HTTP/1.1 {} OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        format!("http://{addr}")
+    }
+
+    pub(crate) fn mock_endpoints(server: &str) -> AuthEndpoints {
+        AuthEndpoints {
+            device_code: format!("{server}/devicecode"),
+            authorize: format!("{server}/authorize"),
+            token: format!("{server}/token"),
+            xbox_user_authenticate: format!("{server}/user/authenticate"),
+            xbox_xsts_authorize: format!("{server}/xsts/authorize"),
+            minecraft_login_with_xbox: format!("{server}/authentication/login_with_xbox"),
+            minecraft_entitlements: format!("{server}/entitlements/mcstore"),
+            minecraft_profile: format!("{server}/minecraft/profile"),
+        }
+    }
+
+    /// The interval is 0, so the tests don't need to wait before exchanging it.
+    fn device_code_json() -> String {
+        json!({
+            "user_code": "ABCD1234",
+            "device_code": "device_code",
+            "verification_uri": "https://www.microsoft.com/link",
+            "expires_in": 900,
+            "interval": 0,
+            "message": "To sign in, use a web browser to open the page"
+        }).to_string()
+    }
+
+    fn xbox_token_json(token: &str) -> String {
+        json!({
+            "IssueInstant": "2024-01-01T00:00:00.0000000Z",
+            "NotAfter": "2024-01-15T00:00:00.0000000Z",
+            "Token": token,
+            "DisplayClaims": {
+                "xui": [{ "uhs": "user_hash", "xid": "2535405290000000", "gtg": "Notch" }]
+            }
+        }).to_string()
+    }
+
+    fn minecraft_token_json() -> String {
+        json!({
+            "username": "a2c0e1b0-0000-0000-0000-000000000000",
+            "roles": [],
+            "access_token": "minecraft_access_token",
+            "token_type": "Bearer",
+            "expires_in": 86400
+        }).to_string()
+    }
+
+    fn entitlements_json(items: &[&str]) -> String {
+        let items: Vec<Value> = items.iter().map(|x| json!({ "name": x, "signature": "signature" })).collect();
+        json!({
+            "items": items,
+            "signature": "signature",
+            "keyId": "1"
+        }).to_string()
+    }
+
+    fn profile_json() -> String {
+        json!({
+            "id": "069a79f444e94726a5befca90e38aaf5",
+            "name": "Notch",
+            "skins": [{
+                "id": "6a6e65e5-76dd-4c3c-a625-162924514568",
+                "state": "ACTIVE",
+                "url": "http://textures.minecraft.net/texture/292009a4925b58f02c77dadc3ecef07ea4c7472f64e0fdc32ce5522489362680",
+                "textureKey": "292009a4925b58f02c77dadc3ecef07ea4c7472f64e0fdc32ce5522489362680",
+                "variant": "CLASSIC"
+            }],
+            "capes": []
+        }).to_string()
+    }
+
+    /// The routes of a server which make every step success.
+    pub(crate) fn success_routes() -> Vec<(&'static str, u16, String)> {
+        vec![
+            ("/devicecode", 200, device_code_json()),
+            ("/token", 200, microsoft_token_json()),
+            ("/user/authenticate", 200, xbox_token_json("xbox_live_token")),
+            ("/xsts/authorize", 200, xbox_token_json("xsts_token")),
+            ("/authentication/login_with_xbox", 200, minecraft_token_json()),
+            ("/entitlements/mcstore", 200, entitlements_json(&["product_minecraft", "game_minecraft"])),
+            ("/minecraft/profile", 200, profile_json()),
+        ]
+    }
+
+    /// Replace the responses of some paths in [success_routes].
+    pub(crate) fn routes_with(overrides: Vec<(&'static str, u16, String)>) -> Vec<(&'static str, u16, String)> {
+        let mut routes = success_routes();
+        routes.retain(|(route, _, _)| overrides.iter().all(|(path, _, _)| route != path));
+        routes.splice(0..0, overrides);
+        routes
+    }
+
+    fn microsoft_token_json() -> String {
+        json!({
+            "token_type": "Bearer",
+            "scope": "XboxLive.signin offline_access",
+            "expires_in": 3600,
+            "ext_expires_in": 3600,
+            "access_token": "microsoft_access_token",
+            "refresh_token": "microsoft_refresh_token"
+        }).to_string()
+    }
+
+    #[test]
+    fn test_code_challenge(){
+        // the example in RFC 7636 Appendix B
+        let challenge = code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk");
+        assert_eq!(challenge, "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+    }
+
+    #[tokio::test]
+    async fn test_authorization_code(){
+        let server = mock_server(vec![("/token", 200, microsoft_token_json())]).await;
+        let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        let listener = flow.generate_authorization_url().await.unwrap();
+        let (redirect_uri, state) = match &flow.status {
+            MinecraftAuthStep::AuthorizationCode(data) => {
+                assert!(data.url.starts_with(&format!("{server}/authorize?")));
+                assert!(data.url.contains("code_challenge_method=S256"));
+                (data.redirect_uri.clone(), data.state.clone())
+            }
+            _ => panic!("the status should be AuthorizationCode"),
+        };
+
+        // act as the browser which is redirected by the OAuth server
+        let port = redirect_uri.rsplit_once(':').unwrap().1.to_string();
+        tokio::spawn(async move {
+            // the connection which never sends a request doesn't block the redirect
+            let _idle = TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+            let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).await.unwrap();
+            let request = format!("GET /?code=test_code&state={state} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200"));
+        });
+
+        flow.exchange_authorization_code(listener).await.unwrap();
+        match &flow.status {
+            MinecraftAuthStep::MicrosoftAuth(data) => {
+                assert_eq!(data.data.access_token, "microsoft_access_token");
+                assert_eq!(data.data.refresh_token, "microsoft_refresh_token");
+            }
+            _ => panic!("the status should be MicrosoftAuth"),
+        }
+    }
+
+    #[test]
+    fn test_offline_uuid(){
+        // the uuid Minecraft server gives to Notch on offline-mode server
+        assert_eq!(offline_uuid("Notch").to_string(), "b50ad385-829d-3141-a216-7e7d7539ba7f");
+
+        let account = Account::offline("Notch");
+        assert_eq!(account.profile.id, "b50ad385829d3141a2167e7d7539ba7f");
+        assert_eq!(account.account_type, AccountType::Offline);
+        assert_eq!(account.account_type.user_type(), "legacy");
+    }
+
+    #[tokio::test]
+    async fn test_device_code_flow(){
+        let server = mock_server(success_routes()).await;
+        let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        flow.generate_device_code().await.unwrap();
+        match &flow.status {
+            MinecraftAuthStep::DeviceCode(data) => assert_eq!(data.data.user_code, "ABCD1234"),
+            _ => panic!("the status should be DeviceCode"),
+        }
+
+        flow.exchange_device_code().await.unwrap();
+        flow.xbox_live_auth().await.unwrap();
+        match &flow.status {
+            MinecraftAuthStep::XboxLiveAuth(_, token) => assert_eq!(token, "xbox_live_token"),
+            _ => panic!("the status should be XboxLiveAuth"),
+        }
+
+        flow.xbox_security_auth().await.unwrap();
+        match &flow.status {
+            MinecraftAuthStep::XboxSecurityAuth(_, token, uhs) => {
+                assert_eq!(token, "xsts_token");
+                assert_eq!(uhs, "user_hash");
+            }
+            _ => panic!("the status should be XboxSecurityAuth"),
+        }
+        let xbox = flow.xbox_profile.clone().unwrap();
+        assert_eq!(xbox.xuid, "2535405290000000");
+        assert_eq!(xbox.gamertag, "Notch");
+
+        let token = flow.get_minecraft_token().await.unwrap();
+        assert_eq!(token.data.access_token, "minecraft_access_token");
+
+        let account = flow.check_minecraft_profile().await.unwrap();
+        assert_eq!(account.profile.name, "Notch");
+        assert_eq!(account.account_type, AccountType::Microsoft);
+        assert!(matches!(flow.status, MinecraftAuthStep::Init()));
+    }
+
+    #[tokio::test]
+    async fn test_login(){
+        let server = mock_server(success_routes()).await;
+        let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        let mut progress = Vec::new();
+        let account = flow.login(|x| progress.push(x)).await.unwrap();
+        assert_eq!(account.profile.name, "Notch");
+        assert!(account.entitlements.unwrap().owns_java());
+        assert!(matches!(flow.status, MinecraftAuthStep::Init()));
+
+        match &progress[0] {
+            LoginProgress::DeviceCode { user_code, verification_uri, .. } => {
+                assert_eq!(user_code, "ABCD1234");
+                assert_eq!(verification_uri, "https://www.microsoft.com/link");
+            }
+            _ => panic!("the first step should be DeviceCode"),
+        }
+        assert_eq!(
+            progress[1..],
+            [
+                LoginProgress::WaitingForUser,
+                LoginProgress::XboxLive,
+                LoginProgress::XboxSecurity,
+                LoginProgress::MinecraftToken,
+                LoginProgress::Profile,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_failed(){
+        let body = json!({ "XErr": 2148916233u64 }).to_string();
+        let server = mock_server(routes_with(vec![("/xsts/authorize", 401, body)])).await;
+        let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        let mut progress = Vec::new();
+        let result = flow.login(|x| progress.push(x)).await;
+        assert!(matches!(result, Err(MinecraftAuthError::XboxAccountNotExist)));
+        assert_eq!(progress.last(), Some(&LoginProgress::XboxSecurity));
+        assert!(matches!(flow.status, MinecraftAuthStep::Init()));
+    }
+
+    #[tokio::test]
+    async fn test_login_sessions(){
+        let server = mock_server(success_routes()).await;
+        let sessions = LoginSessions::new(MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server)));
+
+        let (first, first_flow) = sessions.create().await;
+        let (second, second_flow) = sessions.create().await;
+        assert_ne!(first, second);
+
+        // the first session is waiting for user, it should not block the second one.
+        let mut waiting = first_flow.lock().await;
+        waiting.generate_device_code().await.unwrap();
+        let account = second_flow.lock().await.login(|_| {}).await.unwrap();
+        assert_eq!(account.profile.name, "Notch");
+        assert!(sessions.get(&first).await.is_ok());
+
+        // the device code of first session is expired
+        if let MinecraftAuthStep::DeviceCode(data) = &waiting.status {
+            waiting.status = MinecraftAuthStep::DeviceCode(TimeSensitiveData::new_invalid(data.data.clone()));
+        }
+        drop(waiting);
+        assert!(matches!(sessions.get(&first).await, Err(MinecraftAuthError::SessionNotFound)));
+        assert!(sessions.get(&second).await.is_ok());
+
+        sessions.remove(&second).await;
+        assert!(matches!(sessions.get(&second).await, Err(MinecraftAuthError::SessionNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_step(){
+        let mut flow = MinecraftAuthorizationFlow::new("client_id");
+        let result = flow.xbox_live_auth().await;
+        assert!(matches!(result, Err(MinecraftAuthError::InvalidState)));
+    }
+
+    #[tokio::test]
+    async fn test_device_code_exchange_error(){
+        let cases = vec![
+            ("authorization_pending", "AuthorizationPending"),
+            ("authorization_declined", "AuthorizationDeclined"),
+            ("bad_verification_code", "BadVerificationCode"),
+            ("expired_token", "ExpiredToken"),
+            ("slow_down", "SlowDown"),
+        ];
+
+        for (error, variant) in cases {
+            let body = json!({ "error": error, "error_description": error }).to_string();
+            let server = mock_server(routes_with(vec![("/token", 400, body)])).await;
+            let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+            flow.generate_device_code().await.unwrap();
+            let result = flow.exchange_device_code().await;
+            assert_eq!(format!("{:?}", result.unwrap_err()), variant);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_xsts_error(){
+        let cases = vec![
+            (2148916233u64, "XboxAccountNotExist"),
+            (2148916235, "XboxAccountCountryBan"),
+            (2148916236, "XboxAccountNeedAdultVerification"),
+            (2148916237, "XboxAccountNeedAdultVerification"),
+            (2148916238, "AddToFamily"),
+        ];
+
+        for (xerr, variant) in cases {
+            let body = json!({
+                "Identity": "0",
+                "XErr": xerr,
+                "Message": "",
+                "Redirect": "https://start.ui.xboxlive.com/CreateAccount"
+            }).to_string();
+            let server = mock_server(routes_with(vec![("/xsts/authorize", 401, body)])).await;
+            let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+            let result = flow.login(|_| {}).await;
+            assert_eq!(format!("{:?}", result.unwrap_err()), variant);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_profile_not_found(){
+        let body = json!({
+            "path": "/minecraft/profile",
+            "errorType": "NOT_FOUND",
+            "error": "NOT_FOUND",
+            "errorMessage": "The server has not found anything matching the request URI"
+        }).to_string();
+        let server = mock_server(routes_with(vec![("/minecraft/profile", 404, body)])).await;
+        let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        let result = flow.login(|_| {}).await;
+        match result {
+            Err(MinecraftAuthError::ProfileNotFound(details)) => {
+                assert_eq!(details.status, Some(404));
+                assert_eq!(
+                    details.description.as_deref(),
+                    Some("The server has not found anything matching the request URI")
+                );
+                assert!(details.body.contains("NOT_FOUND"));
+            }
+            _ => panic!("should be ProfileNotFound"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_game_not_owned(){
+        let cases = vec![
+            (vec![], false),
+            (vec!["product_game_pass_pc"], true),
+        ];
+
+        for (items, can_play) in cases {
+            let routes = routes_with(vec![
+                ("/minecraft/profile", 404, String::new()),
+                ("/entitlements/mcstore", 200, entitlements_json(&items)),
+            ]);
+            let server = mock_server(routes).await;
+            let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+            let result = flow.login(|_| {}).await;
+            match can_play {
+                true => assert!(matches!(result, Err(MinecraftAuthError::ProfileNotFound(_)))),
+                false => {
+                    let mut account = result.unwrap();
+                    assert!(account.is_demo());
+                    assert_eq!(account.profile.id, "a2c0e1b0000000000000000000000000");
+                    assert_eq!(account.profile.name, "Notch");
+                    assert!(account.launch_auth(&flow).await.unwrap().demo);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_entitlements_error(){
+        // the account with profile can still login when the entitlements endpoint is down
+        let server = mock_server(routes_with(vec![("/entitlements/mcstore", 500, String::new())])).await;
+        let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        let account = flow.login(|_| {}).await.unwrap();
+        assert_eq!(account.profile.name, "Notch");
+        assert!(account.entitlements.is_none());
+        assert!(!account.is_demo());
+
+        // but it is an error when there is no profile, because we don't know it is a demo account or not
+        let routes = routes_with(vec![
+            ("/minecraft/profile", 404, String::new()),
+            ("/entitlements/mcstore", 500, String::new()),
+        ]);
+        let server = mock_server(routes).await;
+        let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        let result = flow.login(|_| {}).await;
+        assert!(matches!(result, Err(MinecraftAuthError::MinecraftServiceError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_response(){
+        // a 200 response which isn't json should not crash us
+        let server = mock_server(routes_with(vec![("/user/authenticate", 200, "<html></html>".to_string())])).await;
+        let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        let result = flow.login(|_| {}).await;
+        match result {
+            Err(MinecraftAuthError::InvalidResponse(details)) => {
+                assert_eq!(details.status, Some(200));
+                assert_eq!(details.body, "<html></html>");
+            }
+            _ => panic!("should be InvalidResponse"),
+        }
+
+        // the XSTS response without user hash
+        let body = json!({ "Token": "xsts_token", "DisplayClaims": { "xui": [] } }).to_string();
+        let server = mock_server(routes_with(vec![("/xsts/authorize", 200, body)])).await;
+        let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        let result = flow.login(|_| {}).await;
+        assert!(matches!(result, Err(MinecraftAuthError::InvalidResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_error(){
+        let body = json!({ "error": "invalid_grant", "error_description": "AADSTS70000: The grant is invalid" }).to_string();
+        let server = mock_server(routes_with(vec![("/token", 400, body)])).await;
+        let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        flow.generate_device_code().await.unwrap();
+        let err = flow.exchange_device_code().await.unwrap_err();
+        let details = err.details().expect("should carry the response");
+        assert_eq!(details.status, Some(400));
+        assert_eq!(details.description.as_deref(), Some("AADSTS70000: The grant is invalid"));
+
+        // XErr we don't know, or no XErr at all
+        let server = mock_server(routes_with(vec![("/xsts/authorize", 401, String::new())])).await;
+        let mut flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        let result = flow.login(|_| {}).await;
+        match result {
+            Err(MinecraftAuthError::XboxAuthError(details)) => assert_eq!(details.status, Some(401)),
+            _ => panic!("should be XboxAuthError"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh(){
+        let server = mock_server(success_routes()).await;
+        let flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        let mut account = flow.fork().login(|_| {}).await.unwrap();
+
+        assert_eq!(account.xbox.as_ref().unwrap().gamertag, "Notch");
+
+        account.minecraft = None;
+        account.xbox = None;
+        account.refresh(&flow).await.unwrap();
+        assert!(account.minecraft.is_some());
+        assert!(account.xbox.is_some());
+
+        let auth = account.launch_auth(&flow).await.unwrap();
+        assert_eq!(auth.access_token, "minecraft_access_token");
+        assert_eq!(auth.user_type, "msa");
+        assert_eq!(auth.xuid, "2535405290000000");
+    }
+
+    #[test]
+    fn test_account_order(){
+        let mut list = AccountList::default();
+        list.add(Account::offline("Alex"));
+        list.add(Account::offline("Steve"));
+        list.add(Account::offline("Notch"));
+        let names = |list: &AccountList| list.sorted().iter().map(|x| x.profile.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&list), ["Alex", "Steve", "Notch"]);
+
+        let notch = list.0[2].profile.id.clone();
+        list.reorder(std::slice::from_ref(&notch)).unwrap();
+        assert_eq!(names(&list), ["Notch", "Alex", "Steve"]);
+        assert!(list.reorder(&["unknown".to_string()]).is_err());
+
+        // logging in again keeps the order and nickname
+        list.find(&notch).unwrap().set_nickname(Some(" main ".to_string()));
+        list.find(&notch).unwrap().needs_relogin = true;
+        list.add(Account::offline("Notch"));
+        assert!(list.find(&notch).unwrap().needs_relogin);
+        list.relogin(Account::offline("Notch"));
+        assert_eq!(names(&list), ["Notch", "Alex", "Steve"]);
+        assert_eq!(list.find(&notch).unwrap().meta.nickname.as_deref(), Some("main"));
+        assert!(!list.find(&notch).unwrap().needs_relogin);
+
+        list.find(&notch).unwrap().set_nickname(Some("".to_string()));
+        assert_eq!(list.find(&notch).unwrap().meta.nickname, None);
+    }
+
+    #[test]
+    fn test_account_encryption(){
+        let folder = std::env::temp_dir().join(format!("nolauncher-accounts-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("accounts.json");
+        let key = folder.join("accounts.key");
+
+        let mut list = AccountList::default();
+        list.add(Account::offline("Steve"));
+        list.save(&path).unwrap();
+        assert!(!crypto::is_sealed(&std::fs::read(&path).unwrap()));
+
+        // the plain file is migrated
+        list.set_encryption(&path, true).unwrap();
+        assert!(key.exists());
+        assert!(crypto::is_sealed(&std::fs::read(&path).unwrap()));
+        assert_eq!(AccountList::load(&path).unwrap().0[0].profile.name, "Steve");
+
+        // still sealed after saving
+        list.save(&path).unwrap();
+        assert!(crypto::is_sealed(&std::fs::read(&path).unwrap()));
+
+        list.set_encryption(&path, false).unwrap();
+        assert!(!key.exists());
+        assert!(!crypto::is_sealed(&std::fs::read(&path).unwrap()));
+        assert_eq!(AccountList::load(&path).unwrap().0[0].profile.name, "Steve");
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_account_load_failed(){
+        let folder = std::env::temp_dir().join(format!("nolauncher-accounts-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("accounts.json");
+        let key = folder.join("accounts.key");
+
+        let mut list = AccountList::init(&path, true);
+        list.add(Account::offline("Steve"));
+        list.save(&path).unwrap();
+        let sealed = std::fs::read(&path).unwrap();
+        assert!(crypto::is_sealed(&sealed));
+
+        // the key is lost, the sealed file is kept as it is
+        std::fs::remove_file(&key).unwrap();
+        let mut list = AccountList::init(&path, false);
+        assert!(list.0.is_empty());
+        assert!(list.save(&path).is_err());
+        assert!(list.set_encryption(&path, false).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), sealed);
+        assert!(!key.exists());
+
+        // a wrong key doesn't open it either
+        crypto::create_key(&key).unwrap();
+        let list = AccountList::init(&path, true);
+        assert!(list.save(&path).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), sealed);
+
+        // the plain file is sealed by the config, not by whether the key exists
+        std::fs::write(&path, "[]").unwrap();
+        let mut list = AccountList::init(&path, false);
+        assert!(!key.exists());
+        list.add(Account::offline("Alex"));
+        std::fs::write(&key, [0u8; 32]).unwrap();
+        list.save(&path).unwrap();
+        assert!(!crypto::is_sealed(&std::fs::read(&path).unwrap()));
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pending_logins(){
+        let device_code = serde_json::from_str(&device_code_json()).unwrap();
+        let mut pending = PendingLogins::default();
+        pending.0.insert("valid".to_string(), MinecraftAuthStep::DeviceCode(TimeSensitiveData::new(device_code)));
+        let device_code = serde_json::from_str(&device_code_json()).unwrap();
+        pending.0.insert("expired".to_string(), MinecraftAuthStep::DeviceCode(TimeSensitiveData::new_invalid(device_code)));
+        pending.0.insert("init".to_string(), MinecraftAuthStep::Init());
+
+        let json = serde_json::to_string(&pending).unwrap();
+        let mut pending: PendingLogins = serde_json::from_str(&json).unwrap();
+        pending.retain_valid();
+        assert_eq!(pending.0.len(), 1);
+
+        // the session is resumed with the same id and step
+        let step = pending.0.remove("valid").unwrap();
+        let sessions = LoginSessions::new(MinecraftAuthorizationFlow::new("client_id"));
+        sessions.resume("valid", step).await;
+        let flow = sessions.get("valid").await.unwrap();
+        match &flow.lock().await.status {
+            MinecraftAuthStep::DeviceCode(data) => assert_eq!(data.data.user_code, "ABCD1234"),
+            _ => panic!("the step should be DeviceCode"),
+        };
+    }
+}
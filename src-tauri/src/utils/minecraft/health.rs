@@ -0,0 +1,186 @@
+use std::time::Duration;
+use log::warn;
+use crate::utils::minecraft::auth::{Account, AccountType, MinecraftAuthError, MinecraftAuthorizationFlow};
+
+/// The Microsoft token is refreshed when it expires within this, so it is still valid when the user launches.
+const REFRESH_MARGIN: Duration = Duration::from_secs(10 * 60);
+
+/// What [Account::update_health] changed, the account list only needs to be saved when any of them is.
+#[derive(Debug, Default, PartialEq)]
+pub struct HealthChange {
+    pub needs_relogin: bool,
+    pub tokens: bool,
+}
+
+impl HealthChange {
+    pub fn any(&self) -> bool {
+        self.needs_relogin || self.tokens
+    }
+}
+
+impl MinecraftAuthError {
+    /// Whether the user has to login again to fix it, the failures of network or server are not.
+    pub fn needs_relogin(&self) -> bool {
+        match self {
+            MinecraftAuthError::RefreshMicrosoftTokenError(details) => {
+                details.status.is_some_and(|x| (400..500).contains(&x))
+            }
+            MinecraftAuthError::YggdrasilError(e) => e.is_forbidden(),
+            MinecraftAuthError::NotMicrosoftAccount
+            | MinecraftAuthError::ImportedLoginExpired
+            | MinecraftAuthError::XboxAccountNotExist
+            | MinecraftAuthError::XboxAccountCountryBan
+            | MinecraftAuthError::XboxAccountNeedAdultVerification
+            | MinecraftAuthError::AddToFamily => true,
+            _ => false,
+        }
+    }
+}
+
+impl Account {
+    /// Make sure the tokens of account still work, the Microsoft token is refreshed when it is near expiry,
+    /// and the profile is fetched again. Remember to save the [crate::utils::minecraft::auth::AccountList] after this.
+    pub async fn check_health(&mut self, flow: &MinecraftAuthorizationFlow) -> Result<(), MinecraftAuthError> {
+        match self.account_type {
+            AccountType::Offline => Ok(()),
+            AccountType::Microsoft => {
+                // the imported account works until its Minecraft token expires, but we can't renew it
+                if self.msa.is_none() {
+                    return Err(MinecraftAuthError::ImportedLoginExpired);
+                }
+                if self.msa.as_ref().is_some_and(|x| x.expires_within(REFRESH_MARGIN)) {
+                    self.refresh(flow).await?;
+                }
+                // the demo account has no profile on Minecraft services
+                if self.is_demo() {
+                    return self.minecraft_token(flow).await.map(|_| ());
+                }
+                self.refresh_profile(flow).await
+            }
+            AccountType::Yggdrasil => self.yggdrasil_token().await.map(|_| ()),
+        }
+    }
+
+    /// The access tokens of account, they are different after any of them is refreshed.
+    pub fn access_tokens(&self) -> [Option<String>; 3] {
+        [
+            self.msa.as_ref().map(|x| x.data.access_token.clone()),
+            self.minecraft.as_ref().map(|x| x.data.access_token.clone()),
+            self.yggdrasil.as_ref().map(|x| x.access_token.clone()),
+        ]
+    }
+
+    /// Run [Account::check_health] and update `needs_relogin` by the result.
+    /// The flag is kept when the check failed by other reasons, for example, there is no network.
+    pub async fn update_health(&mut self, flow: &MinecraftAuthorizationFlow) -> HealthChange {
+        let tokens = self.access_tokens();
+        let needs_relogin = match self.check_health(flow).await {
+            Ok(()) => false,
+            Err(e) => {
+                warn!("The account {} is not healthy: {}", self.profile.name, e);
+                e.needs_relogin() || self.needs_relogin
+            }
+        };
+
+        let change = HealthChange {
+            needs_relogin: self.needs_relogin != needs_relogin,
+            tokens: self.access_tokens() != tokens,
+        };
+        self.needs_relogin = needs_relogin;
+        change
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use crate::utils::minecraft::auth::test::mock_server;
+    use crate::utils::minecraft::auth::{Account, MinecraftAuthError, MinecraftAuthorizationFlow, ResponseDetails};
+    use crate::utils::minecraft::health::HealthChange;
+    use crate::utils::minecraft::yggdrasil::{YggdrasilError, YggdrasilProfile, YggdrasilSession};
+
+    #[test]
+    fn test_needs_relogin() {
+        let details = |status: Option<u16>| ResponseDetails {
+            status,
+            ..Default::default()
+        };
+
+        assert!(MinecraftAuthError::RefreshMicrosoftTokenError(details(Some(400))).needs_relogin());
+        assert!(!MinecraftAuthError::RefreshMicrosoftTokenError(details(Some(503))).needs_relogin());
+        assert!(!MinecraftAuthError::RequestError(details(None)).needs_relogin());
+        assert!(MinecraftAuthError::NotMicrosoftAccount.needs_relogin());
+        assert!(MinecraftAuthError::ImportedLoginExpired.needs_relogin());
+        assert!(MinecraftAuthError::YggdrasilError(YggdrasilError::InvalidToken).needs_relogin());
+        let server_error = |status: u16| YggdrasilError::Server {
+            status,
+            error: String::new(),
+            message: String::new(),
+        };
+        assert!(MinecraftAuthError::YggdrasilError(server_error(403)).needs_relogin());
+        assert!(!MinecraftAuthError::YggdrasilError(server_error(503)).needs_relogin());
+        assert!(!MinecraftAuthError::YggdrasilError(YggdrasilError::Request("timeout".to_string())).needs_relogin());
+    }
+
+    #[tokio::test]
+    async fn test_update_health() {
+        let flow = MinecraftAuthorizationFlow::new("client_id");
+
+        let mut account = Account::offline("Steve");
+        assert!(!account.update_health(&flow).await.any());
+        assert!(!account.needs_relogin);
+
+        // a Microsoft account without any token
+        let mut account = Account::offline("Steve");
+        account.account_type = Default::default();
+        assert_eq!(account.update_health(&flow).await, HealthChange { needs_relogin: true, tokens: false });
+        assert!(account.needs_relogin);
+        assert!(!account.update_health(&flow).await.any());
+    }
+
+    async fn yggdrasil_account(routes: Vec<(&'static str, u16, String)>) -> Account {
+        let session = YggdrasilSession {
+            api_root: mock_server(routes).await,
+            access_token: "access_token".to_string(),
+            client_token: "client_token".to_string(),
+        };
+        let profile = YggdrasilProfile {
+            id: "5627dd98e6be3c21b8a8e92344183641".to_string(),
+            name: "Steve".to_string(),
+        };
+        Account::yggdrasil(session, profile)
+    }
+
+    #[tokio::test]
+    async fn test_yggdrasil_health() {
+        let flow = MinecraftAuthorizationFlow::new("client_id");
+        let forbidden = json!({ "error": "ForbiddenOperationException", "errorMessage": "Invalid token." }).to_string();
+
+        // the server is down, the account is not flagged
+        let mut account = yggdrasil_account(vec![
+            ("/authserver/validate", 503, String::new()),
+            ("/authserver/refresh", 503, String::new()),
+        ])
+        .await;
+        assert!(!account.update_health(&flow).await.any());
+        assert!(!account.needs_relogin);
+
+        // the token is refreshed
+        let refresh = json!({ "accessToken": "new_token", "clientToken": "client_token" }).to_string();
+        let mut account = yggdrasil_account(vec![
+            ("/authserver/validate", 403, forbidden.clone()),
+            ("/authserver/refresh", 200, refresh),
+        ])
+        .await;
+        assert_eq!(account.update_health(&flow).await, HealthChange { needs_relogin: false, tokens: true });
+        assert_eq!(account.yggdrasil.unwrap().access_token, "new_token");
+
+        // the token can't be refreshed anymore
+        let mut account = yggdrasil_account(vec![
+            ("/authserver/validate", 403, forbidden.clone()),
+            ("/authserver/refresh", 403, forbidden),
+        ])
+        .await;
+        assert_eq!(account.update_health(&flow).await, HealthChange { needs_relogin: true, tokens: false });
+    }
+}
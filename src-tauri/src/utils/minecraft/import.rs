@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use chrono::{DateTime, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::utils::data::TimeSensitiveData;
+use crate::utils::minecraft::auth::{Account, AccountType, MicrosoftAuthResponse, MinecraftAuthError, MinecraftAuthResponse, MinecraftAuthorizationFlow, MinecraftProfile, SafeAccountList};
+
+/// The launcher which the accounts are imported from.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSource {
+    /// `accounts.json` in the data folder of Prism Launcher.
+    Prism,
+    /// `launcher_accounts_microsoft_store.json` or `launcher_accounts.json` in `.minecraft` of the official launcher.
+    Vanilla,
+}
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("Failed to read the accounts file. details:{0}")]
+    Io(#[from] std::io::Error),
+    #[error("The accounts file is not in the format we know. details:{0}")]
+    Format(#[from] serde_json::Error),
+}
+
+/// An account found in the file of other launcher, `account` is the reason when we can't use it.
+#[derive(Debug)]
+pub struct ImportCandidate {
+    pub id: String,
+    pub name: String,
+    pub account: Result<Account, String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "status")]
+pub enum ImportStatus {
+    /// The account is validated and added, `expires_at` is when it needs to login again,
+    /// it is only set when the launcher didn't give us a refresh token we can use, and the account
+    /// is flagged with `needs_relogin` then.
+    Imported { expires_at: Option<String> },
+    /// There is already an account with the same profile.
+    Exists,
+    Failed { error: String },
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportReport {
+    pub id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub status: ImportStatus,
+}
+
+#[derive(Deserialize)]
+struct PrismAccounts {
+    accounts: Vec<PrismAccount>,
+}
+
+#[derive(Deserialize)]
+struct PrismAccount {
+    #[serde(rename = "type")]
+    account_type: String,
+    profile: Option<PrismProfile>,
+    msa: Option<PrismToken>,
+    /// The Minecraft token, it is still called `ygg` since the time of Mojang accounts.
+    ygg: Option<PrismToken>,
+}
+
+#[derive(Deserialize)]
+struct PrismProfile {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct PrismToken {
+    #[serde(default)]
+    token: String,
+    refresh_token: Option<String>,
+    /// The unix time when the token expires.
+    #[serde(default)]
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VanillaAccounts {
+    accounts: HashMap<String, VanillaAccount>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VanillaAccount {
+    #[serde(rename = "type", default)]
+    account_type: String,
+    access_token: Option<String>,
+    access_token_expires_at: Option<String>,
+    minecraft_profile: Option<PrismProfile>,
+}
+
+fn profile(id: &str, name: &str) -> MinecraftProfile {
+    MinecraftProfile {
+        id: id.replace('-', ""),
+        name: name.to_string(),
+        skins: vec![],
+        capes: vec![],
+    }
+}
+
+/// An account which only has the Minecraft token, the real profile is fetched when validating.
+fn microsoft(id: &str, name: &str, token: &str, expires_at: DateTime<Local>) -> Account {
+    let expires_in = (expires_at - Local::now()).to_std().unwrap_or(Duration::ZERO);
+    let minecraft = TimeSensitiveData::new(MinecraftAuthResponse {
+        username: id.to_string(),
+        access_token: token.to_string(),
+        expires_in,
+        token_type: "Bearer".to_string(),
+    });
+    Account::microsoft(profile(id, name), minecraft)
+}
+
+pub fn read_prism(content: &str) -> Result<Vec<ImportCandidate>, ImportError> {
+    let file: PrismAccounts = serde_json::from_str(content)?;
+
+    let candidates = file
+        .accounts
+        .into_iter()
+        .filter_map(|x| {
+            let profile = x.profile?;
+            let account = match x.account_type.as_str() {
+                "Offline" => Ok(Account::offline(&profile.name)),
+                "MSA" => {
+                    let (token, exp) = x.ygg.map(|x| (x.token, x.exp)).unwrap_or_default();
+                    let expires_at = Local.timestamp_opt(exp, 0).single().unwrap_or_default();
+                    let mut account = microsoft(&profile.id, &profile.name, &token, expires_at);
+                    account.msa = x.msa.and_then(|x| x.refresh_token).map(|refresh_token| {
+                        TimeSensitiveData::new_invalid(MicrosoftAuthResponse {
+                            token_type: "Bearer".to_string(),
+                            scope: String::new(),
+                            expires_in: Duration::ZERO,
+                            ext_expires_in: 0,
+                            access_token: String::new(),
+                            refresh_token,
+                        })
+                    });
+                    Ok(account)
+                }
+                other => Err(format!("The {} account is not supported", other)),
+            };
+
+            Some(ImportCandidate {
+                id: profile.id,
+                name: profile.name,
+                account,
+            })
+        })
+        .collect();
+
+    Ok(candidates)
+}
+
+/// The official launcher keeps the Microsoft tokens in the credential manager of system,
+/// so only the Minecraft token in the file can be imported.
+pub fn read_vanilla(content: &str) -> Result<Vec<ImportCandidate>, ImportError> {
+    let file: VanillaAccounts = serde_json::from_str(content)?;
+
+    let candidates = file
+        .accounts
+        .into_values()
+        .filter_map(|x| {
+            let profile = x.minecraft_profile?;
+            let expires_at = x
+                .access_token_expires_at
+                .and_then(|x| DateTime::parse_from_rfc3339(&x).ok())
+                .map(|x| x.with_timezone(&Local));
+
+            let account = match (x.account_type.as_str(), x.access_token, expires_at) {
+                ("Xbox", Some(token), Some(expires_at)) if !token.is_empty() => {
+                    Ok(microsoft(&profile.id, &profile.name, &token, expires_at))
+                }
+                ("Xbox", _, _) => Err("The launcher didn't store the token in file, please login again".to_string()),
+                (other, _, _) => Err(format!("The {} account is not supported", other)),
+            };
+
+            Some(ImportCandidate {
+                id: profile.id,
+                name: profile.name,
+                account,
+            })
+        })
+        .collect();
+
+    Ok(candidates)
+}
+
+pub fn read_file(source: ImportSource, path: &Path) -> Result<Vec<ImportCandidate>, ImportError> {
+    let content = std::fs::read_to_string(path)?;
+    match source {
+        ImportSource::Prism => read_prism(&content),
+        ImportSource::Vanilla => read_vanilla(&content),
+    }
+}
+
+/// Try to refresh the account, and fetch the real profile of Microsoft account.
+async fn validate(account: &mut Account, flow: &MinecraftAuthorizationFlow) -> Result<(), String> {
+    if account.account_type != AccountType::Microsoft {
+        return Ok(());
+    }
+
+    if account.msa.is_some() {
+        if let Err(e) = account.refresh(flow).await {
+            // the refresh token is bound to the client id of other launcher,
+            // but the Minecraft token still works until it expires.
+            log::warn!("Failed to refresh the imported account {}: {}", account.profile.name, e);
+            account.msa = None;
+        }
+    }
+
+    if account.msa.is_none() {
+        if !account.minecraft.as_ref().is_some_and(|x| x.is_vaild()) {
+            return Err(MinecraftAuthError::ImportedLoginExpired.to_string());
+        }
+        // it is shown as needing to login again, since it stops working when the token expires
+        account.needs_relogin = true;
+    }
+
+    account.refresh_profile(flow).await.map_err(|e| e.to_string())
+}
+
+/// Validate the candidates and add the valid ones to `accounts`, the accounts we already have are skipped.
+/// The list is only locked to check and add the account, not during the validation.
+pub async fn import(
+    accounts: &SafeAccountList,
+    candidates: Vec<ImportCandidate>,
+    flow: &MinecraftAuthorizationFlow,
+) -> Vec<ImportReport> {
+    let mut reports = vec![];
+
+    for candidate in candidates {
+        let id = match &candidate.account {
+            Ok(account) => account.profile.id.clone(),
+            Err(_) => candidate.id.replace('-', ""),
+        };
+        let exists = accounts.read().await.get(&id).is_some();
+        let status = match candidate.account {
+            _ if exists => ImportStatus::Exists,
+            Err(error) => ImportStatus::Failed { error },
+            Ok(mut account) => match validate(&mut account, flow).await {
+                Ok(()) => {
+                    let expires_at = match (&account.msa, &account.minecraft) {
+                        (None, Some(token)) => Some((token.time + token.data.expires_in).to_rfc3339()),
+                        _ => None,
+                    };
+
+                    // it may be added by user during the validation
+                    let mut accounts = accounts.write().await;
+                    if accounts.get(&account.profile.id).is_some() {
+                        ImportStatus::Exists
+                    } else {
+                        accounts.add(account);
+                        ImportStatus::Imported { expires_at }
+                    }
+                }
+                Err(error) => ImportStatus::Failed { error },
+            },
+        };
+
+        reports.push(ImportReport {
+            id,
+            name: candidate.name,
+            status,
+        });
+    }
+
+    reports
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+    use tokio::sync::RwLock;
+    use crate::utils::minecraft::auth::test::{mock_endpoints, mock_server, routes_with, success_routes};
+    use crate::utils::minecraft::auth::{AccountList, AccountType, MinecraftAuthError, MinecraftAuthorizationFlow};
+    use crate::utils::minecraft::import::{import, read_prism, read_vanilla, ImportStatus};
+
+    fn prism_json() -> String {
+        json!({
+            "formatVersion": 3,
+            "accounts": [
+                {
+                    "type": "MSA",
+                    "active": true,
+                    "msa": {"token": "msa_access_token", "refresh_token": "prism_refresh_token", "exp": 0},
+                    "ygg": {"token": "minecraft_access_token", "exp": 4102444800i64},
+                    "profile": {"id": "069a79f444e94726a5befca90e38aaf5", "name": "Notch", "capes": []}
+                },
+                {
+                    "type": "Offline",
+                    "ygg": {"token": "0", "exp": 0},
+                    "profile": {"id": "5627dd98e6be3c21b8a8e92344183641", "name": "Steve"}
+                },
+                {
+                    "type": "Mojang",
+                    "profile": {"id": "853c80ef3c3749fdaa49938b674adae6", "name": "jeb_"}
+                }
+            ]
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_read_prism() {
+        let candidates = read_prism(&prism_json()).unwrap();
+        assert_eq!(candidates.len(), 3);
+
+        let notch = candidates[0].account.as_ref().unwrap();
+        assert_eq!(notch.account_type, AccountType::Microsoft);
+        assert_eq!(notch.msa.as_ref().unwrap().data.refresh_token, "prism_refresh_token");
+        assert!(!notch.msa.as_ref().unwrap().is_vaild());
+        assert!(notch.minecraft.as_ref().unwrap().is_vaild());
+
+        let steve = candidates[1].account.as_ref().unwrap();
+        assert_eq!(steve.account_type, AccountType::Offline);
+        assert_eq!(steve.profile.name, "Steve");
+
+        assert!(candidates[2].account.is_err());
+        assert!(read_prism("{}").is_err());
+    }
+
+    #[test]
+    fn test_read_vanilla() {
+        let content = json!({
+            "accounts": {
+                "a": {
+                    "type": "Xbox",
+                    "accessToken": "minecraft_access_token",
+                    "accessTokenExpiresAt": "2000-01-01T00:00:00.000Z",
+                    "minecraftProfile": {"id": "069a79f4-44e9-4726-a5be-fca90e38aaf5", "name": "Notch"},
+                    "username": "notch@example.com"
+                },
+                "b": {
+                    "type": "Xbox",
+                    "minecraftProfile": {"id": "853c80ef3c3749fdaa49938b674adae6", "name": "jeb_"}
+                },
+                "c": {
+                    "type": "Xbox",
+                    "username": "no profile"
+                }
+            },
+            "activeAccountLocalId": "a"
+        })
+        .to_string();
+
+        let mut candidates = read_vanilla(&content).unwrap();
+        candidates.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(candidates.len(), 2);
+
+        let notch = candidates[0].account.as_ref().unwrap();
+        assert_eq!(notch.profile.id, "069a79f444e94726a5befca90e38aaf5");
+        assert!(notch.msa.is_none());
+        // expired in 2000
+        assert!(!notch.minecraft.as_ref().unwrap().is_vaild());
+
+        assert!(candidates[1].account.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import() {
+        let accounts = RwLock::new(AccountList::default());
+        let mut candidates = read_prism(&prism_json()).unwrap();
+        // the Microsoft account needs network, it is tested by the tests below
+        candidates.remove(0);
+        let steve = candidates[0].id.clone();
+
+        let flow = MinecraftAuthorizationFlow::new("client_id");
+        let reports = import(&accounts, candidates, &flow).await;
+        assert_eq!(reports[0].status, ImportStatus::Imported { expires_at: None });
+        assert!(matches!(reports[1].status, ImportStatus::Failed { .. }));
+        assert!(accounts.read().await.get(&steve).is_some());
+
+        let reports = import(&accounts, read_prism(&prism_json()).unwrap().split_off(1), &flow).await;
+        assert_eq!(reports[0].status, ImportStatus::Exists);
+        assert_eq!(accounts.read().await.0.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_import_refreshed() {
+        let server = mock_server(success_routes()).await;
+        let flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+        let accounts = RwLock::new(AccountList::default());
+
+        let candidates = read_prism(&prism_json()).unwrap().into_iter().take(1).collect();
+        let reports = import(&accounts, candidates, &flow).await;
+        assert_eq!(reports[0].status, ImportStatus::Imported { expires_at: None });
+
+        let accounts = accounts.read().await;
+        let notch = accounts.get("069a79f444e94726a5befca90e38aaf5").unwrap();
+        assert_eq!(notch.msa.as_ref().unwrap().data.refresh_token, "microsoft_refresh_token");
+        assert_eq!(notch.profile.skins.len(), 1);
+        assert!(!notch.needs_relogin);
+    }
+
+    #[tokio::test]
+    async fn test_import_refresh_failed() {
+        // the refresh token of Prism is bound to its client id
+        let body = json!({ "error": "invalid_grant", "error_description": "AADSTS70000: The grant is invalid" }).to_string();
+        let server = mock_server(routes_with(vec![("/token", 400, body)])).await;
+        let flow = MinecraftAuthorizationFlow::new("client_id").with_endpoints(mock_endpoints(&server));
+
+        // the Minecraft token still works until it expires
+        let accounts = RwLock::new(AccountList::default());
+        let candidates = read_prism(&prism_json()).unwrap().into_iter().take(1).collect();
+        let reports = import(&accounts, candidates, &flow).await;
+        assert!(matches!(reports[0].status, ImportStatus::Imported { expires_at: Some(_) }));
+        let notch = accounts.read().await.get("069a79f444e94726a5befca90e38aaf5").unwrap().clone();
+        assert!(notch.msa.is_none());
+        assert!(notch.needs_relogin);
+        assert_eq!(notch.minecraft.unwrap().data.access_token, "minecraft_access_token");
+
+        // and it is rejected when the token is expired
+        let mut content: Value = serde_json::from_str(&prism_json()).unwrap();
+        content["accounts"][0]["ygg"]["exp"] = json!(0);
+        let accounts = RwLock::new(AccountList::default());
+        let candidates = read_prism(&content.to_string()).unwrap().into_iter().take(1).collect();
+        let reports = import(&accounts, candidates, &flow).await;
+        match &reports[0].status {
+            ImportStatus::Failed { error } => assert_eq!(error, &MinecraftAuthError::ImportedLoginExpired.to_string()),
+            status => panic!("unexpected status {:?}", status),
+        }
+        assert!(accounts.read().await.0.is_empty());
+    }
+}
@@ -5,7 +5,9 @@ use std::path::{PathBuf};
 use std::sync::{Arc};
 use std::sync::atomic::{AtomicI64};
 use serde::{Deserialize, Serialize};
-use crate::utils::minecraft::metadata::{AssetIndex, decode_hex, equal_my_platform, Library, MetadataSetting, rules_analyzer, string2platform};
+use crate::utils::minecraft::arguments::Argument;
+use crate::utils::minecraft::metadata::{AssetIndex, decode_hex, equal_my_platform, Environment, Extract, Library, MetadataSetting, rules_analyzer, string2platform};
+use crate::utils::minecraft::natives::native_classifier;
 use crate::utils::minecraft::metadata::Library::Common;
 use crate::utils::minecraft::metadata::SHAType::SHA256;
 use anyhow::Result;
@@ -26,7 +28,10 @@ pub struct InstanceConfig{
     #[serde(default)]
     pub dep: HashMap<String,String>, // key: uid, value: version,
     #[serde(default)]
-    pub top: String // top dep uid
+    pub top: String, // top dep uid
+    /// The uuid of account to launch with, the active user is used when this is None.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
 }
 
 #[derive(Debug,PartialEq,Clone,Hash,Eq)]
@@ -34,7 +39,8 @@ pub enum FileType {
     Lib,
     Client,
     Installer, // for forge, neoforge only.
-    Asset
+    Asset,
+    Native(Extract), // the jar is unpacked to natives directory, instead of being on classpath.
 }
 
 impl Default for FileType {
@@ -49,7 +55,12 @@ pub struct LaunchData{
     pub main_class: String,
     pub dep: Vec<Library>,
     pub asset_index: AssetIndex,
-    pub launch_args: String
+    pub game_args: Vec<Argument>,
+    /// Only the versions since 1.13 have jvm arguments.
+    pub jvm_args: Vec<Argument>,
+    pub version_type: Option<String>,
+    /// The version of `net.minecraft`, which is `${version_name}` of arguments.
+    pub version_name: String,
 }
 
 impl LaunchData {
@@ -198,6 +209,28 @@ impl GameFile {
                     )
                 }
 
+                // the libraries with `natives` need to be extracted, like lwjgl 2.
+                if !lib.natives.is_empty(){
+                    let classifier = native_classifier(&lib.natives, &Environment::host());
+                    let native = classifier.and_then(|x| lib.downloads.classifiers.get(&x));
+
+                    if let Some(v) = native{
+                        let filename = v.url.rsplit_once('/').unwrap().1.to_string();
+
+                        vec.push(
+                            GameFile {
+                                path:path.clone(),
+                                filename,
+                                url: v.url.clone(),
+                                file_type:FileType::Native(lib.extract.clone().unwrap_or_default()),
+                                size:Some(v.size)
+                            }
+                        )
+                    }
+
+                    return vec
+                }
+
                 for (platform,v) in lib.downloads.clone().classifiers{
                     let platform = string2platform(&platform);
                     if equal_my_platform(&Some(platform)){
@@ -275,6 +308,9 @@ pub async fn get_launch_data(config: &MetadataSetting, instance_config: &Instanc
     let mut asset_index = None;
     let mut launch_args = None;
     let mut default_launch_args = None;
+    let mut jvm_args = vec![];
+    let mut version_type = None;
+    let mut version_name = String::new();
 
     for (uid,version) in pkg.iter(){
         let pkg_info = config
@@ -323,13 +359,18 @@ pub async fn get_launch_data(config: &MetadataSetting, instance_config: &Instanc
             dep.push(i.clone())
         }
         
+        let arguments = version_details.get_arguments();
+
         if uid == "net.minecraft"{
-            default_launch_args = version_details.minecraft_arguments.clone()
+            default_launch_args = arguments.clone();
+            jvm_args = arguments.clone().map(|x| x.jvm).unwrap_or_default();
+            version_type = version_details.type_.clone();
+            version_name = version.clone();
         }
 
         if uid == &instance_config.top{
             main_class = version_details.main_class.clone();
-            launch_args = version_details.minecraft_arguments
+            launch_args = arguments
         }
 
         if let Some(client_lib) = &version_details.main_jar{
@@ -345,7 +386,10 @@ pub async fn get_launch_data(config: &MetadataSetting, instance_config: &Instanc
         main_class:main_class.unwrap(),
         dep,
         asset_index:asset_index.unwrap(),
-        launch_args:launch_args.unwrap_or(default_launch_args.unwrap())
+        game_args:launch_args.or(default_launch_args).map(|x| x.game).unwrap_or_default(),
+        jvm_args,
+        version_type,
+        version_name,
     })
 }
 
@@ -355,12 +399,16 @@ pub async fn get_launch_data(config: &MetadataSetting, instance_config: &Instanc
 /// 2. Preparing -> fetching metadata and get launch information from it.
 /// 3. Downloading -> Downloading the game file instance need.
 /// 4. Checking -> Checking the game file is valid!
-/// 5. Running -> the game is running.
+/// 5. Running -> the game is running, `demo` is true when it is running in demo mode.
 /// 6. Failed -> the game start failed!
 #[derive(Clone, Serialize)]
 #[serde(tag = "type")]
 pub enum Status{
-    Running(#[serde(skip)] Arc<CommandChild>),
+    Running{
+        #[serde(skip)]
+        child:Arc<CommandChild>,
+        demo:bool
+    },
     Preparing,
     Checking{now:Arc<AtomicI64>,total:i64}, // (the file amount has been checked, total)
     Downloading{now:Arc<AtomicI64>,total:i64}, // (the amount of data has been download, total)
@@ -391,7 +439,7 @@ impl SafeInstanceStatus{
         let status = &self.0.read().await;
         let status = status.get(key).unwrap_or(&Status::Stopped);
         match status {
-            Status::Running(_) => {"Running"}
+            Status::Running { .. } => {"Running"}
             Status::Preparing => {"Preparing"}
             Status::Checking { .. } => {"Checking"}
             Status::Downloading { .. } => {"Downloading"}
@@ -400,11 +448,16 @@ impl SafeInstanceStatus{
         }.to_string()
     }
     
+    pub async fn is_demo(&self, key:&str) -> bool {
+        let status = &self.0.read().await;
+        matches!(status.get(key), Some(Status::Running { demo: true, .. }))
+    }
+
     pub async fn can_start(&self, key:&str) -> bool {
         let status = &self.0.read().await;
         let status = status.get(key).unwrap_or(&Status::Stopped);
         match status {
-            Status::Running(_) => {false}
+            Status::Running { .. } => {false}
             Status::Preparing => {false}
             Status::Checking { .. } => {false}
             Status::Downloading { .. } => {false}
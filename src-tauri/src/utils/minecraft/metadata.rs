@@ -3,19 +3,24 @@ use std::io::ErrorKind;
 use std::io::ErrorKind::NotFound;
 use std::num::ParseIntError;
 use std::path::PathBuf;
+use std::sync::OnceLock;
 use std::time::Duration;
-use serde::{de, Deserialize, Deserializer, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use sha1::{Digest as d1,Sha1};
 use sha2::{Digest, Sha256};
 use thiserror::Error;
 use crate::utils::data::{TimeSensitiveData, TimeSensitiveTrait};
 use anyhow::Result;
-use serde_json::Value;
+use log::warn;
+use regex::Regex;
 use tauri::AppHandle;
 use tokio::fs::create_dir_all;
 use nolauncher_derive::Load;
 use crate::constant::ASSET_INDEX_ROOT;
 use crate::utils::config::Load;
+use crate::utils::minecraft::arguments::{Arguments, Features};
+use crate::utils::minecraft::piston::PistonMeta;
+use crate::utils::minecraft::source::{MetadataSource, Source};
 
 #[derive(Debug,Clone,Serialize,Deserialize,PartialEq,Default)]
 #[serde(rename_all = "camelCase")]
@@ -85,9 +90,9 @@ pub struct VersionInfo{
         skip_serializing_if = "Vec::is_empty",
         default
     )]
-    conflicts:Vec<DependencyPackage>,
+    pub(crate) conflicts:Vec<DependencyPackage>,
     pub version:String,
-    volatile: Option<bool>
+    pub(crate) volatile: Option<bool>
 }
 
 /// For package details, like: minecraft, fabric-loader, etc.
@@ -95,9 +100,9 @@ pub struct VersionInfo{
 #[derive(Debug,Clone,Deserialize,PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct PackageDetails {
-    format_version:i32,
-    name:String,
-    uid:String,
+    pub(crate) format_version:i32,
+    pub(crate) name:String,
+    pub(crate) uid:String,
     pub(crate) versions:Vec<VersionInfo>
 }
 
@@ -114,11 +119,111 @@ pub enum Platform{
     LinuxArm32,
     #[serde(rename = "linux-arm64")]
     LinuxArm64,
+    #[serde(rename = "osx")]
+    MacOs,
     #[serde(rename = "osx-arm64")]
     MacOsArm64,
+    #[serde(other)]
     Unknown
 }
 
+impl Platform {
+    /// Check the platform is the os and arch of `env`, the name without arch (like `windows`) means x86 or x86_64,
+    /// except `osx` which means x86_64 only, because Apple Silicon has its own `osx-arm64`.
+    /// It is used for the classifiers of natives, the rules use [Platform::matches_rule].
+    pub fn matches(&self, env:&Environment) -> bool{
+        let x86 = env.arch == "x86" || env.arch == "x86_64";
+        match self {
+            Platform::Windows => env.os == "windows" && x86,
+            Platform::WindowsArm64 => env.os == "windows" && env.arch == "arm64",
+            Platform::Linux => env.os == "linux" && x86,
+            Platform::LinuxArm32 => env.os == "linux" && env.arch == "arm32",
+            Platform::LinuxArm64 => env.os == "linux" && env.arch == "arm64",
+            Platform::MacOs => env.os == "osx" && env.arch == "x86_64",
+            Platform::MacOsArm64 => env.os == "osx" && env.arch == "arm64",
+            Platform::Unknown => false,
+        }
+    }
+
+    /// Check the platform of `os.name` in rule, the name without arch only checks the os like piston-meta does,
+    /// and the name with arch (like `osx-arm64` of Prism's meta) checks the arch too.
+    pub fn matches_rule(&self, env:&Environment) -> bool{
+        match self {
+            Platform::Windows => env.os == "windows",
+            Platform::Linux => env.os == "linux",
+            Platform::MacOs => env.os == "osx",
+            _ => self.matches(env),
+        }
+    }
+}
+
+/// The platform and features to check the rules with. [Environment::host] is the computer running the launcher,
+/// and the others can be built for exporting an instance to another platform.
+///
+/// `os` is one of `windows`, `linux` and `osx`, `arch` is one of `x86`, `x86_64`, `arm32` and `arm64`,
+/// `version` is the version of os, which is checked by the regex of `os.version` in rule.
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct Environment{
+    pub os:String,
+    pub arch:String,
+    pub version:String,
+    pub features:Features,
+}
+
+impl Environment {
+    pub fn host() -> Self{
+        let os = match std::env::consts::OS {
+            "macos" => "osx",
+            os => os,
+        };
+
+        Self{
+            os: os.to_string(),
+            arch: normalize_arch(std::env::consts::ARCH).to_string(),
+            version: host_os_version().to_string(),
+            features: Features::default(),
+        }
+    }
+}
+
+/// The arch names used by Mojang, Prism and Rust are different, so they are converted to the same one.
+fn normalize_arch(arch:&str) -> &str{
+    match arch {
+        "x86" | "i386" | "i686" => "x86",
+        "x86_64" | "amd64" => "x86_64",
+        "arm" | "arm32" => "arm32",
+        "aarch64" | "arm64" => "arm64",
+        arch => arch,
+    }
+}
+
+/// The version of os, same as `os.version` of Java, which is checked by the rules, it is empty if we can't get it.
+fn host_os_version() -> &'static str{
+    static VERSION: OnceLock<String> = OnceLock::new();
+
+    VERSION.get_or_init(|| {
+        let output = match std::env::consts::OS {
+            "linux" => return std::fs::read_to_string("/proc/sys/kernel/osrelease")
+                .map(|x| x.trim().to_string())
+                .unwrap_or_default(),
+            "macos" => std::process::Command::new("sw_vers").arg("-productVersion").output(),
+            "windows" => std::process::Command::new("cmd").args(["/c", "ver"]).output(),
+            _ => return String::new(),
+        };
+
+        // the output of `ver` is like `Microsoft Windows [Version 10.0.19045.4529]`
+        output
+            .map(|x| String::from_utf8_lossy(&x.stdout).to_string())
+            .unwrap_or_default()
+            .trim()
+            .trim_end_matches(']')
+            .rsplit(' ')
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    })
+}
+
 
 /// to check if you are on argument platform.
 ///
@@ -134,33 +239,9 @@ pub enum Platform{
 ///
 /// ```
 pub fn equal_my_platform(platform:&Option<Platform>) -> bool{
-
-    if let Some(platform) = platform{
-        match platform {
-            Platform::Windows => {
-                cfg!(target_arch = "x86") || cfg!(target_arch = "x86_64") && cfg!(target_os = "windows")
-            }
-            Platform::WindowsArm64 => {
-                cfg!(target_arch = "aarch64") && cfg!(target_os = "windows")
-            }
-            Platform::Linux => {
-                cfg!(target_arch = "x86") || cfg!(target_arch = "x86_64") && cfg!(target_os = "linux")
-            }
-            Platform::LinuxArm32 => {
-                cfg!(target_arch = "arm") && cfg!(target_os = "linux")
-            }
-            Platform::LinuxArm64 => {
-                cfg!(target_arch = "aarch64") && cfg!(target_os = "linux")
-            }
-            Platform::MacOsArm64 => {
-                cfg!(target_arch = "aarch64") && cfg!(target_os = "macos")
-            }
-            Platform::Unknown => {
-                false // we don't support x86 macos
-            }
-        }
-    } else {
-        true
+    match platform {
+        Some(platform) => platform.matches(&Environment::host()),
+        None => true,
     }
 }
 
@@ -170,8 +251,9 @@ pub fn string2platform(classifier:&str) -> Platform{
         "natives-windows" => Platform::Windows,
         "natives-linux-arm32" => Platform::LinuxArm32,
         "natives-linux-arm64" => Platform::LinuxArm64,
-        "natives-osx-arm64" => Platform::MacOsArm64,
-        _ => Platform::Unknown // we don't support x86 macos
+        "natives-osx" | "natives-macos" => Platform::MacOs,
+        "natives-osx-arm64" | "natives-macos-arm64" => Platform::MacOsArm64,
+        _ => Platform::Unknown
     }
 
 }
@@ -181,8 +263,54 @@ pub fn string2platform(classifier:&str) -> Platform{
 #[derive(Debug,Clone,Deserialize,PartialEq)]
 pub struct Rule{
     action:Action,
-    #[serde(deserialize_with = "os_processing", default)]
-    os:Option<Platform>
+    #[serde(default)]
+    os:Option<OsRule>,
+    /// The rule is only matched when all the features are same as [Features], it is used by arguments.
+    #[serde(default)]
+    features:HashMap<String,bool>
+}
+
+/// The `os` of rule, every field given should be matched.
+#[derive(Debug,Clone,Deserialize,PartialEq,Default)]
+pub struct OsRule{
+    name:Option<Platform>,
+    arch:Option<String>,
+    /// The regex of os version, like `^10\.` for Windows 10.
+    version:Option<String>,
+}
+
+impl From<Platform> for OsRule {
+    fn from(value: Platform) -> Self {
+        Self{
+            name: Some(value),
+            ..Default::default()
+        }
+    }
+}
+
+impl OsRule {
+    pub fn matches(&self, env:&Environment) -> bool{
+        let name = self.name.as_ref().is_none_or(|x| x.matches_rule(env));
+        let arch = self.arch.as_ref().is_none_or(|x| normalize_arch(x) == env.arch);
+        let version = self.version.as_ref().is_none_or(|x| match Regex::new(x) {
+            Ok(regex) => regex.is_match(&env.version),
+            Err(e) => {
+                warn!("Invalid regex of os version in rule: {}", e);
+                false
+            }
+        });
+
+        name && arch && version
+    }
+}
+
+impl Rule {
+    pub fn matches(&self, env:&Environment) -> bool{
+        let os = self.os.as_ref().is_none_or(|x| x.matches(env));
+        let features = self.features.iter().all(|(name, value)| env.features.get(name) == *value);
+
+        os && features
+    }
 }
 
 /// Allow mean this rule is allow on the rule's platform, disallow mean this rule is disallow on the rule's platform.
@@ -194,31 +322,6 @@ pub enum Action{
     Disallow
 }
 
-/// This function is used to deserialize the os field in Rule struct.
-fn os_processing<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Platform>, D::Error> {
-
-    return Ok(match Value::deserialize(deserializer)?{
-        Value::Object(map) => {
-            if let Some(Value::String(osname)) = map.get("name"){
-
-                match osname.to_string().as_str(){
-                    "windows" => Some(Platform::Windows),   
-                    "windows-arm64" => Some(Platform::WindowsArm64),
-                    "linux" => Some(Platform::Linux),
-                    "linux-arm32" => Some(Platform::LinuxArm32),
-                    "linux-arm64" => Some(Platform::LinuxArm64),
-                    "osx-arm64" => Some(Platform::MacOsArm64),
-                    _ => Some(Platform::Unknown)
-                }
-            } else {
-                None
-            }
-
-        }
-        _ => return Err(de::Error::custom("Invalid type"))
-    })
-}
-
 /// This struct is used to store the artifact information of a library or client.
 #[derive(Debug,Clone,Deserialize,PartialEq)]
 pub struct Artifact{
@@ -238,10 +341,10 @@ pub struct Download{
 }
 
 /// This struct is used to store the extract information of a library.
-#[derive(Debug,Clone,Deserialize,PartialEq,Hash,Eq)]
+#[derive(Debug,Clone,Deserialize,PartialEq,Hash,Eq,Default)]
 pub struct Extract{
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
-    exclude:Vec<String>
+    pub exclude:Vec<String>
 }
 
 /// This struct is used to store the common library information.
@@ -270,17 +373,23 @@ pub struct CommonLibrary {
 /// let test_case = vec![Rule{
 ///     action: Action::Allow,
 ///     os: None,
+///     features: HashMap::new(),
 /// }];
 /// let test_result = rules_analyzer(test_case);
 /// assert!(test_result);
 ///
 /// ```
 pub fn rules_analyzer(rules:Vec<Rule>) -> bool{
+    rules_analyzer_for(&rules, &Environment::host())
+}
+
+/// Same as [rules_analyzer], but the rules are checked with `env` instead of the host.
+pub fn rules_analyzer_for(rules:&[Rule], env:&Environment) -> bool{
 
     let mut allow = rules.is_empty(); // if empty all allow, if not disallow.
 
     for rule in rules.iter(){
-        if equal_my_platform(&rule.os){
+        if rule.matches(env){
             if rule.action == Action::Disallow {
                 return false; // your platform is not allow
             }else{
@@ -387,9 +496,21 @@ pub struct VersionDetails {
     pub main_class:Option<String>,
     pub main_jar:Option<CommonLibrary>,
     pub minecraft_arguments:Option<String>,
+    /// The arguments since 1.13, only piston-meta has this, Prism's meta always uses `minecraft_arguments`.
+    #[serde(default)]
+    pub arguments:Option<Arguments>,
     pub asset_index:Option<AssetIndex>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub compatible_java_majors:Vec<i32>,
 } 
 
+impl VersionDetails {
+    /// The typed arguments, `minecraft_arguments` is converted when there is no `arguments`.
+    pub fn get_arguments(&self) -> Option<Arguments> {
+        self.arguments.clone().or_else(|| self.minecraft_arguments.as_deref().map(Arguments::from_legacy))
+    }
+}
+
 /* the function to handle metadata */
 pub fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
     (0..s.len())
@@ -420,8 +541,13 @@ pub enum SHAType{
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MetadataSetting{
+    /// The root of metadata repository, it can be `file://` url of a local mirror, see [Source].
     api:String,
     cache_override:Option<PathBuf>,
+    /// Read `net.minecraft` from Mojang's piston-meta at this root instead, so the new versions can be launched
+    /// before Prism's meta has them. It is [crate::utils::minecraft::piston::PISTON_META] or a `file://` url of mirror.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub piston_api:Option<String>,
     pub package_list: TimeSensitiveData<PackageList>
 }
 
@@ -430,6 +556,7 @@ impl Default for MetadataSetting{
         MetadataSetting{
             api: "https://meta.prismlauncher.org/v1/".to_string(),
             cache_override: None,
+            piston_api: None,
             package_list: TimeSensitiveData::new_invalid(PackageList::default()),
         }
     }
@@ -486,18 +613,14 @@ impl MetadataSetting{
         }
     }
 
-    pub async fn get_package_details(&self,default:PathBuf,uid:&str,sha:SHAType) -> Result<PackageDetails>{
-        let cache_root = self.cache_override.clone().unwrap_or({
-            default
-        });
-        
-        let path = cache_root.join(uid);
-        let file = path.join("index.json");
-        let url = format!("{}/{}/index.json",self.api,uid);
-
-
-        Self::check_and_create_folder(path.clone()).await?;
+    /// The source of metadata files, see [Source::from_url].
+    pub fn source(&self) -> Source {
+        Source::from_url(&self.api)
+    }
 
+    /// Read the file of `path` in cache folder, it is fetched from `source` again when it is
+    /// missing or the sha is not matched.
+    pub(crate) async fn get_cached_or_fetch(source:&impl MetadataSource, file:PathBuf, path:&str, sha:SHAType) -> Result<String>{
         let mut count = 0;
 
         loop{
@@ -505,96 +628,74 @@ impl MetadataSetting{
                 return Err(MetadataFileError::RetryTooManyTime.into())
             }
             count+=1;
-            let content = Self::get_cached_file_content(file.clone(),sha.clone()).await;
-            return match content {
-                Ok(str) => {
-                    Ok(serde_json::from_str(&str).unwrap())
-                }
-                Err(error) => {
-                    if let MetadataFileError::IO(e) = error {
-                        if let NotFound = e{
-                            let url = format!("{}/{}",self.api,uid);
-                            fetch_and_store(file.clone(),&url).await?;
-                            continue;
-                        }
-                        return Err(MetadataFileError::IO(e).into())
-                    }
-
-                    if let MetadataFileError::Invalid = error{
-                        fetch_and_store(file.clone(),&url).await?;
-                        continue
-                    }
-
-                    return Err(error.into())
 
+            match Self::get_cached_file_content(file.clone(),sha.clone()).await {
+                Ok(str) => return Ok(str),
+                Err(MetadataFileError::IO(NotFound)) | Err(MetadataFileError::Invalid) => {
+                    let content = source.fetch(path).await?;
+                    tokio::fs::write(&file,content).await.map_err(|e| MetadataFileError::IO(e.kind()))?;
                 }
+                Err(error) => return Err(error.into())
             }
         }
     }
 
-    pub async fn get_version_details(&self,default:PathBuf,uid:&str,version:&str,sha:SHAType) -> Result<VersionDetails>{
-        let cache_root = self.cache_override.clone().unwrap_or({
-            default
-        });
-
-        let path = cache_root.join(uid);
-        let file = path.join(format!("{}.json",version));
-        let url = format!("{}/{}/{}.json",self.api,uid,version);
+    /// The piston-meta for `uid`, only `net.minecraft` is read from it.
+    fn piston(&self, uid:&str) -> Option<PistonMeta<Source>>{
+        self.piston_api.as_ref()
+            .filter(|_| uid == "net.minecraft")
+            .map(|api| PistonMeta::new(Source::from_url(api)))
+    }
 
-        Self::check_and_create_folder(path.clone()).await?;
-        let mut count = 0;
+    fn piston_cache(&self, default:PathBuf) -> PathBuf{
+        self.cache_override.clone().unwrap_or(default).join("piston-meta")
+    }
 
-        loop{
+    pub async fn get_package_details(&self,default:PathBuf,uid:&str,sha:SHAType) -> Result<PackageDetails>{
+        if let Some(piston) = self.piston(uid){
+            return piston.package_details(&self.piston_cache(default)).await
+        }
+        self.get_package_details_from(&self.source(), default, uid, sha).await
+    }
 
-            if count > 3{
-                return Err(MetadataFileError::RetryTooManyTime.into())
-            }
-            count+=1;
+    pub async fn get_package_details_from(&self,source:&impl MetadataSource,default:PathBuf,uid:&str,sha:SHAType) -> Result<PackageDetails>{
+        let cache_root = self.cache_override.clone().unwrap_or(default);
+        let path = cache_root.join(uid);
+        Self::check_and_create_folder(path.clone()).await?;
 
-            let content = Self::get_cached_file_content(file.clone(),sha.clone()).await;
-            return match content {
-                Ok(str) => {
-                    Ok(serde_json::from_str(&str).unwrap())
-                }
-                Err(error) => {
-                    if let MetadataFileError::IO(e) = error {
-                        if let NotFound = e{    
-                            fetch_and_store(file.clone(),&url).await?;
-                            continue;
-                        }
-                        return Err(MetadataFileError::IO(e).into())
-                    }
+        let content = Self::get_cached_or_fetch(source, path.join("index.json"), &format!("{}/index.json",uid), sha).await?;
+        Ok(serde_json::from_str(&content)?)
+    }
 
-                    if let MetadataFileError::Invalid = error{
-                        fetch_and_store(file.clone(),&url).await?;
-                    }
+    /// The `sha` is not used for piston-meta, the sha1 in its manifest is checked instead.
+    pub async fn get_version_details(&self,default:PathBuf,uid:&str,version:&str,sha:SHAType) -> Result<VersionDetails>{
+        if let Some(piston) = self.piston(uid){
+            return piston.version_details(&self.piston_cache(default), version).await
+        }
+        self.get_version_details_from(&self.source(), default, uid, version, sha).await
+    }
 
-                    return Err(error.into())
+    pub async fn get_version_details_from(&self,source:&impl MetadataSource,default:PathBuf,uid:&str,version:&str,sha:SHAType) -> Result<VersionDetails>{
+        let cache_root = self.cache_override.clone().unwrap_or(default);
+        let path = cache_root.join(uid);
+        Self::check_and_create_folder(path.clone()).await?;
 
-                }
-            }
-        }
+        let file = path.join(format!("{}.json",version));
+        let content = Self::get_cached_or_fetch(source, file, &format!("{}/{}.json",uid,version), sha).await?;
+        Ok(serde_json::from_str(&content)?)
     }
     
     pub async fn refresh(&mut self) -> Result<(),MetadataFileError>{
-        
-        let res = reqwest::get(&self.api).await;
-        match res {
-            Ok(res) => {
-                let pkg = res.json::<PackageList>().await;
-                
-                match pkg {
-                    Ok(content) => {
-                        self.package_list = TimeSensitiveData::new(content);
-                        Ok(())
-                    }
-                    Err(e) => {
-                        Err(MetadataFileError::Unknown(e.to_string()))
-                    }
-                }
-            }
-            Err(_) => {Err(MetadataFileError::Fetching)}
-        }
+        self.refresh_from(&self.source()).await
+    }
+
+    /// Fetch the [PackageList] from `index.json` of repository.
+    pub async fn refresh_from(&mut self, source:&impl MetadataSource) -> Result<(),MetadataFileError>{
+        let content = source.fetch("index.json").await?;
+        let pkg = serde_json::from_str::<PackageList>(&content)
+            .map_err(|e| MetadataFileError::Unknown(e.to_string()))?;
+        self.package_list = TimeSensitiveData::new(pkg);
+        Ok(())
     }
 
 }
@@ -605,12 +706,18 @@ impl MetadataSetting{
 #[cfg(test)]
 mod test{
     use std::{env, fs};
+    use std::collections::HashMap;
     use std::io::ErrorKind;
     use serde_json::json;
-    use crate::utils::minecraft::metadata::{VersionDetails, PackageDetails, Rule, decode_hex, MetadataFileError, MetadataSetting, Action, rules_analyzer, Platform};
+    use crate::utils::minecraft::metadata::{VersionDetails, PackageDetails, Rule, decode_hex, MetadataFileError, MetadataSetting, Action, rules_analyzer, Platform, rules_analyzer_for, Environment};
+    use crate::utils::minecraft::arguments::Features;
     use crate::utils::minecraft::metadata::SHAType::{SHA1, SHA256};
+    use crate::utils::minecraft::source::LocalSource;
+    use reqwest::Url;
+    use std::path::PathBuf;
 
     #[tokio::test]
+    #[ignore = "it needs the network, run it by `cargo test -- --ignored`"]
     async fn test_version_info(){
 
         let test_api = vec![
@@ -655,6 +762,7 @@ mod test{
     }
 
     #[tokio::test]
+    #[ignore = "it needs the network, run it by `cargo test -- --ignored`"]
     async fn test_package_details(){
 
         let test_api = vec![
@@ -677,6 +785,7 @@ mod test{
     }
 
     #[tokio::test]
+    #[ignore = "it needs the network, run it by `cargo test -- --ignored`"]
     async fn test_package_info(){
         let url = "https://meta.prismlauncher.org/v1/";
         let _res = reqwest::get(url).await.unwrap().json::<crate::utils::minecraft::metadata::PackageList>().await.unwrap();
@@ -725,6 +834,7 @@ mod test{
 
     
     #[tokio::test]
+    #[ignore = "it needs the network, run it by `cargo test -- --ignored`"]
     async fn fetch_package_details(){
         let path = env::current_dir().unwrap();
         let test_path = path.join("test");
@@ -740,6 +850,7 @@ mod test{
     }
 
     #[tokio::test]
+    #[ignore = "it needs the network, run it by `cargo test -- --ignored`"]
     async fn fetch_version_details(){
         let path = env::current_dir().unwrap();
         let test_path = path.join("test2");
@@ -755,11 +866,79 @@ mod test{
     }
 
     #[tokio::test]
+    #[ignore = "it needs the network, run it by `cargo test -- --ignored`"]
     async fn refresh_all(){
         let mut metadata = MetadataSetting::default();
         metadata.refresh().await.unwrap();
     }
 
+    /// The same as the tests above, but with the files in fixtures, so they run without network.
+    #[test]
+    fn test_parse_fixtures(){
+        let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join("metadata");
+        let read = |path: &str| fs::read_to_string(fixtures.join(path)).unwrap();
+
+        let list = serde_json::from_str::<crate::utils::minecraft::metadata::PackageList>(&read("index.json")).unwrap();
+        assert!(list.packages.contains_key("org.lwjgl"));
+        let details = serde_json::from_str::<PackageDetails>(&read("org.lwjgl/index.json")).unwrap();
+        assert_eq!(details.versions[0].version, "2.9.1");
+        let version = serde_json::from_str::<VersionDetails>(&read("org.lwjgl/2.9.1.json")).unwrap();
+        assert_eq!(version.version, "2.9.1");
+    }
+
+    #[tokio::test]
+    async fn test_local_metadata(){
+        let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join("metadata");
+        let cache = env::temp_dir().join(format!("nolauncher-metadata-{}", uuid::Uuid::new_v4()));
+        let mut metadata = MetadataSetting{
+            api: Url::from_directory_path(&fixtures).unwrap().to_string(),
+            cache_override: Some(cache.clone()),
+            ..Default::default()
+        };
+
+        metadata.refresh().await.unwrap();
+        let package = metadata.package_list.data.packages.get("org.lwjgl").unwrap().clone();
+
+        let sha = SHA256(decode_hex(&package.sha256).unwrap());
+        let details = metadata.get_package_details(PathBuf::new(), "org.lwjgl", sha).await.unwrap();
+        let version = &details.versions[0];
+        assert_eq!(version.version, "2.9.1");
+
+        // the broken cache is fetched again
+        tokio::fs::write(cache.join("org.lwjgl").join("2.9.1.json"), "{}").await.unwrap();
+        let sha = SHA256(decode_hex(&version.sha256).unwrap());
+        let details = metadata.get_version_details(PathBuf::new(), "org.lwjgl", "2.9.1", sha.clone()).await.unwrap();
+        assert_eq!(details.libraries.len(), 2);
+
+        // the cache is used when the source doesn't have it
+        let source = LocalSource::new(env::temp_dir().join("not found"));
+        metadata.get_version_details_from(&source, PathBuf::new(), "org.lwjgl", "2.9.1", sha).await.unwrap();
+        let sha = SHA256(decode_hex(&package.sha256).unwrap());
+        tokio::fs::remove_file(cache.join("org.lwjgl").join("index.json")).await.unwrap();
+        assert!(metadata.get_package_details_from(&source, PathBuf::new(), "org.lwjgl", sha).await.is_err());
+
+        tokio::fs::remove_dir_all(cache).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_piston_metadata(){
+        let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join("piston");
+        let cache = env::temp_dir().join(format!("nolauncher-metadata-{}", uuid::Uuid::new_v4()));
+        let metadata = MetadataSetting{
+            piston_api: Some(Url::from_directory_path(&fixtures).unwrap().to_string()),
+            cache_override: Some(cache.clone()),
+            ..Default::default()
+        };
+
+        // the sha of Prism's meta is not used for piston-meta
+        let details = metadata.get_package_details(PathBuf::new(), "net.minecraft", SHA256(vec![])).await.unwrap();
+        assert_eq!(details.versions.len(), 2);
+        let details = metadata.get_version_details(PathBuf::new(), "net.minecraft", "1.21", SHA256(vec![])).await.unwrap();
+        assert_eq!(details.version, "1.21");
+
+        tokio::fs::remove_dir_all(cache).await.unwrap();
+    }
+
     #[cfg(target_arch = "x86_64")]
     #[cfg(target_os = "linux")]
     #[test]
@@ -767,6 +946,7 @@ mod test{
         let test_case = vec![Rule{
                 action: Action::Allow,
                 os: None,
+                features: HashMap::new(),
             }];
         let test_result = rules_analyzer(test_case);
         assert!(test_result);
@@ -775,10 +955,12 @@ mod test{
             Rule{
                 action: Action::Allow,
                 os: None,
+                features: HashMap::new(),
             },
             Rule{
                 action: Action::Disallow,
-                os:Some(Platform::Linux)
+                os: Some(Platform::Linux.into()),
+                features: HashMap::new(),
             }
         ];
         let test_result = rules_analyzer(test_case);
@@ -787,15 +969,100 @@ mod test{
         let test_case = vec![
             Rule{
                 action: Action::Disallow,
-                os: Some(Platform::MacOsArm64),
+                os: Some(Platform::MacOsArm64.into()),
+                features: HashMap::new(),
             },
             Rule{
                 action: Action::Allow,
-                os:Some(Platform::Linux)
+                os: Some(Platform::Linux.into()),
+                features: HashMap::new(),
             }
         ];
         let test_result = rules_analyzer(test_case);
         assert!(test_result);
     }
-    
+
+    fn rules_with_os(name: &str) -> Vec<Rule> {
+        serde_json::from_value(json!([{"action": "allow", "os": {"name": name}}])).unwrap()
+    }
+
+    #[test]
+    fn test_rules_for_environment(){
+        let windows = Environment{
+            os: "windows".to_string(),
+            arch: "x86_64".to_string(),
+            version: "10.0".to_string(),
+            ..Default::default()
+        };
+        let linux_x86 = Environment{
+            os: "linux".to_string(),
+            arch: "x86".to_string(),
+            version: "6.8.0".to_string(),
+            ..Default::default()
+        };
+        let mac_arm = Environment{
+            os: "osx".to_string(),
+            arch: "arm64".to_string(),
+            version: "14.5".to_string(),
+            ..Default::default()
+        };
+
+        // x86 linux shouldn't match the windows rule
+        let rules: Vec<Rule> = serde_json::from_value(json!([{"action": "allow", "os": {"name": "windows"}}])).unwrap();
+        assert!(rules_analyzer_for(&rules, &windows));
+        assert!(!rules_analyzer_for(&rules, &linux_x86));
+        assert!(!rules_analyzer_for(&rules, &mac_arm));
+
+        let rules: Vec<Rule> = serde_json::from_value(json!([
+            {"action": "allow"},
+            {"action": "disallow", "os": {"name": "osx", "version": "^10\\.5\\.\\d$"}}
+        ])).unwrap();
+        assert!(rules_analyzer_for(&rules, &mac_arm));
+        let old_mac = Environment{ arch: "x86_64".to_string(), version: "10.5.8".to_string(), ..mac_arm.clone() };
+        assert!(!rules_analyzer_for(&rules, &old_mac));
+
+        let rules: Vec<Rule> = serde_json::from_value(json!([{"action": "allow", "os": {"arch": "x86"}}])).unwrap();
+        assert!(rules_analyzer_for(&rules, &linux_x86));
+        assert!(!rules_analyzer_for(&rules, &windows));
+
+        // the name without arch matches any arch of that os
+        let windows_arm = Environment{ arch: "arm64".to_string(), ..windows.clone() };
+        let linux_arm = Environment{ arch: "arm64".to_string(), ..linux_x86.clone() };
+        let mac_x86 = Environment{ arch: "x86_64".to_string(), ..mac_arm.clone() };
+        assert!(rules_analyzer_for(&rules_with_os("windows"), &windows_arm));
+        assert!(rules_analyzer_for(&rules_with_os("linux"), &linux_arm));
+        assert!(rules_analyzer_for(&rules_with_os("osx"), &mac_arm));
+        assert!(rules_analyzer_for(&rules_with_os("osx"), &mac_x86));
+
+        // and the name with arch of Prism's meta only matches that arch
+        assert!(rules_analyzer_for(&rules_with_os("osx-arm64"), &mac_arm));
+        assert!(!rules_analyzer_for(&rules_with_os("osx-arm64"), &mac_x86));
+        assert!(rules_analyzer_for(&rules_with_os("linux-arm64"), &linux_arm));
+        assert!(!rules_analyzer_for(&rules_with_os("linux-arm64"), &linux_x86));
+        assert!(rules_analyzer_for(&rules_with_os("windows-arm64"), &windows_arm));
+        assert!(!rules_analyzer_for(&rules_with_os("windows-arm64"), &windows));
+        let rules: Vec<Rule> = serde_json::from_value(json!([
+            {"action": "allow"},
+            {"action": "disallow", "os": {"name": "osx-arm64"}}
+        ])).unwrap();
+        assert!(rules_analyzer_for(&rules, &mac_x86));
+        assert!(!rules_analyzer_for(&rules, &mac_arm));
+
+        // the classifiers of natives still tell the arch by the name
+        assert!(!Platform::Linux.matches(&linux_arm));
+        assert!(Platform::LinuxArm64.matches(&linux_arm));
+        assert!(!Platform::MacOs.matches(&mac_arm));
+
+        // the unknown os is never matched
+        let rules: Vec<Rule> = serde_json::from_value(json!([{"action": "allow", "os": {"name": "solaris"}}])).unwrap();
+        assert!(!rules_analyzer_for(&rules, &linux_x86));
+
+        let rules: Vec<Rule> = serde_json::from_value(json!([
+            {"action": "allow", "features": {"has_custom_resolution": true}}
+        ])).unwrap();
+        assert!(!rules_analyzer_for(&rules, &windows));
+        let features = Features{ has_custom_resolution: true, ..Default::default() };
+        assert!(rules_analyzer_for(&rules, &Environment{ features, ..windows }));
+    }
+
 }
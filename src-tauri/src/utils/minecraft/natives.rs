@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File};
+use std::io::ErrorKind;
+use std::path::Path;
+use anyhow::Result;
+use log::warn;
+use zip::ZipArchive;
+use crate::utils::minecraft::instance::{FileType, GameFile};
+use crate::utils::minecraft::metadata::Environment;
+
+/// Find the classifier of native jar in `natives` of library, like `{"linux": "natives-linux",
+/// "windows": "natives-windows-${arch}"}`. Prism's meta has the key with arch for arm, like `linux-arm64`,
+/// which is used before the one only has os name.
+pub fn native_classifier(natives: &HashMap<String, String>, env: &Environment) -> Option<String> {
+    let classifier = natives
+        .get(&format!("{}-{}", env.os, env.arch))
+        .or_else(|| natives.get(&env.os))?;
+
+    // `${arch}` is the bits of arch, only lwjgl 2 of windows uses it.
+    let bits = match env.arch.as_str() {
+        "x86_64" | "arm64" => "64",
+        _ => "32",
+    };
+
+    Some(classifier.replace("${arch}", bits))
+}
+
+/// Unpack all native jars of `files` into `dir`, the entries start with `exclude` of the library
+/// (like `META-INF/`) are skipped.
+pub fn extract_natives(files: &[GameFile], dir: &Path) -> Result<()> {
+    create_dir_all(dir)?;
+
+    for file in files {
+        if let FileType::Native(extract) = &file.file_type {
+            extract_jar(&file.get_fullpath(), dir, &extract.exclude)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_jar(jar: &Path, dir: &Path, exclude: &[String]) -> Result<()> {
+    let mut archive = ZipArchive::new(File::open(jar)?)?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+
+        // the path out of `dir` (like `../`) is skipped too
+        let Some(name) = entry.enclosed_name() else {
+            continue;
+        };
+
+        let excluded = exclude.iter().any(|x| entry.name().starts_with(x.as_str()));
+        if entry.is_dir() || excluded {
+            continue;
+        }
+
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        std::io::copy(&mut entry, &mut File::create(path)?)?;
+    }
+
+    Ok(())
+}
+
+/// The natives directory is removed after the game stopped, because it is only used by that launch.
+pub async fn clean_natives(dir: &Path) {
+    match tokio::fs::remove_dir_all(dir).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            warn!("Failed to remove natives directory {:?}: {}", dir, e)
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+    use crate::utils::minecraft::instance::{FileType, GameFile};
+    use crate::utils::minecraft::metadata::{Environment, Extract};
+    use crate::utils::minecraft::natives::{clean_natives, extract_natives, native_classifier};
+
+    fn environment(os: &str, arch: &str) -> Environment {
+        Environment {
+            os: os.to_string(),
+            arch: arch.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_native_classifier() {
+        let natives = HashMap::from([
+            ("linux".to_string(), "natives-linux".to_string()),
+            ("linux-arm64".to_string(), "natives-linux-arm64".to_string()),
+            ("windows".to_string(), "natives-windows-${arch}".to_string()),
+        ]);
+
+        assert_eq!(native_classifier(&natives, &environment("linux", "x86_64")).unwrap(), "natives-linux");
+        assert_eq!(native_classifier(&natives, &environment("linux", "arm64")).unwrap(), "natives-linux-arm64");
+        assert_eq!(native_classifier(&natives, &environment("windows", "x86_64")).unwrap(), "natives-windows-64");
+        assert_eq!(native_classifier(&natives, &environment("windows", "x86")).unwrap(), "natives-windows-32");
+        assert!(native_classifier(&natives, &environment("osx", "x86_64")).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_extract_natives() {
+        let root = env::temp_dir().join(format!("nolauncher-natives-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+
+        let mut jar = ZipWriter::new(File::create(root.join("lwjgl-natives.jar")).unwrap());
+        for name in ["liblwjgl.so", "META-INF/MANIFEST.MF", "linux/libopenal.so"] {
+            jar.start_file(name, SimpleFileOptions::default()).unwrap();
+            jar.write_all(name.as_bytes()).unwrap();
+        }
+        jar.finish().unwrap();
+
+        let files = vec![
+            GameFile {
+                path: root.clone(),
+                filename: "lwjgl-natives.jar".to_string(),
+                url: String::new(),
+                file_type: FileType::Native(Extract { exclude: vec!["META-INF/".to_string()] }),
+                size: None,
+            },
+            // the jar on classpath is not extracted
+            GameFile {
+                path: root.clone(),
+                filename: "not-found.jar".to_string(),
+                url: String::new(),
+                file_type: FileType::Lib,
+                size: None,
+            },
+        ];
+
+        let dir = root.join("natives");
+        extract_natives(&files, &dir).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("liblwjgl.so")).unwrap(), "liblwjgl.so");
+        assert!(dir.join("linux").join("libopenal.so").exists());
+        assert!(!dir.join("META-INF").exists());
+
+        clean_natives(&dir).await;
+        assert!(!dir.exists());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use log::warn;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use crate::utils::minecraft::arguments::Arguments;
+use crate::utils::minecraft::metadata::{
+    decode_hex, Artifact, AssetIndex, CommonLibrary, Download, Library, MetadataSetting, PackageDetails, SHAType,
+    VersionDetails, VersionInfo,
+};
+use crate::utils::minecraft::source::MetadataSource;
+
+/// The root of Mojang's piston-meta, which is used by the official launcher.
+pub const PISTON_META: &str = "https://piston-meta.mojang.com/";
+const MANIFEST_PATH: &str = "mc/game/version_manifest_v2.json";
+const MINECRAFT_UID: &str = "net.minecraft";
+
+/// The manifest is fetched again after this, so the new snapshot can be found soon.
+const MANIFEST_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// The `version_manifest_v2.json`, which lists all versions of Minecraft.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VersionManifest {
+    pub latest: LatestVersion,
+    pub versions: Vec<ManifestVersion>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LatestVersion {
+    pub release: String,
+    pub snapshot: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestVersion {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// The url of [PistonVersion].
+    pub url: String,
+    pub release_time: String,
+    pub sha1: String,
+}
+
+/// The json of a version in piston-meta, only the fields we need are here.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PistonVersion {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub release_time: String,
+    pub main_class: String,
+    /// The arguments before 1.13.
+    pub minecraft_arguments: Option<String>,
+    /// The arguments since 1.13, which replace `minecraftArguments`.
+    pub arguments: Option<Arguments>,
+    pub asset_index: AssetIndex,
+    pub downloads: PistonDownloads,
+    #[serde(default)]
+    pub libraries: Vec<Library>,
+    pub java_version: Option<JavaVersion>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct PistonDownloads {
+    pub client: Artifact,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JavaVersion {
+    pub component: String,
+    pub major_version: i32,
+}
+
+impl From<VersionManifest> for PackageDetails {
+    fn from(value: VersionManifest) -> Self {
+        let versions = value
+            .versions
+            .into_iter()
+            .map(|x| VersionInfo {
+                recommended: x.type_ == "release",
+                release_time: x.release_time,
+                // piston-meta only gives sha1, which is checked by PistonMeta::version_details
+                sha256: String::new(),
+                rtype: Some(x.type_),
+                requires: vec![],
+                conflicts: vec![],
+                version: x.id,
+                volatile: None,
+            })
+            .collect();
+
+        PackageDetails {
+            format_version: 1,
+            name: "Minecraft".to_string(),
+            uid: MINECRAFT_UID.to_string(),
+            versions,
+        }
+    }
+}
+
+impl From<PistonVersion> for VersionDetails {
+    fn from(value: PistonVersion) -> Self {
+        let main_jar = CommonLibrary {
+            name: format!("com.mojang:minecraft:{}:client", value.id),
+            downloads: Download {
+                artifact: Some(value.downloads.client),
+                classifiers: HashMap::new(),
+            },
+            rules: vec![],
+            extract: None,
+            natives: HashMap::new(),
+        };
+
+        VersionDetails {
+            format_version: 1,
+            conflicts: vec![],
+            // the libraries of LWJGL are in `libraries`, so it doesn't need `org.lwjgl` like Prism's meta.
+            requires: vec![],
+            libraries: value.libraries,
+            maven_files: vec![],
+            name: "Minecraft".to_string(),
+            uid: MINECRAFT_UID.to_string(),
+            release_time: value.release_time,
+            type_: Some(value.type_),
+            version: value.id,
+            volatile: None,
+            main_class: Some(value.main_class),
+            main_jar: Some(main_jar),
+            minecraft_arguments: value.minecraft_arguments,
+            arguments: value.arguments,
+            asset_index: Some(value.asset_index),
+            compatible_java_majors: value.java_version.map(|x| vec![x.major_version]).unwrap_or_default(),
+        }
+    }
+}
+
+/// Read `net.minecraft` from piston-meta, and convert it to the types of Prism's meta,
+/// so they can be used in the same way.
+pub struct PistonMeta<S> {
+    source: S,
+}
+
+impl<S: MetadataSource> PistonMeta<S> {
+    pub fn new(source: S) -> Self {
+        Self { source }
+    }
+
+    /// Get the manifest, the cached one is used when it is fetched recently or we can't fetch it now.
+    pub async fn manifest(&self, cache: &Path) -> Result<VersionManifest> {
+        tokio::fs::create_dir_all(cache).await?;
+        let file = cache.join("version_manifest_v2.json");
+
+        let fresh = tokio::fs::metadata(&file)
+            .await
+            .and_then(|x| x.modified())
+            .is_ok_and(|x| x.elapsed().is_ok_and(|x| x < MANIFEST_DURATION));
+
+        let content = match fresh {
+            true => tokio::fs::read_to_string(&file).await?,
+            false => match self.source.fetch(MANIFEST_PATH).await {
+                Ok(content) => {
+                    tokio::fs::write(&file, &content).await?;
+                    content
+                }
+                Err(e) => {
+                    warn!("Failed to fetch the version manifest, use the cached one: {}", e);
+                    tokio::fs::read_to_string(&file).await.map_err(|_| e)?
+                }
+            },
+        };
+
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub async fn package_details(&self, cache: &Path) -> Result<PackageDetails> {
+        Ok(self.manifest(cache).await?.into())
+    }
+
+    pub async fn version_details(&self, cache: &Path, version: &str) -> Result<VersionDetails> {
+        let manifest = self.manifest(cache).await?;
+        let info = manifest
+            .versions
+            .iter()
+            .find(|x| x.id == version)
+            .ok_or(anyhow!("The version {} is not found in piston-meta", version))?;
+
+        // the mirror has the same layout as piston-meta, so only the path of url is used.
+        let url = Url::parse(&info.url)?;
+        let path = url.path().trim_start_matches('/');
+        let sha = SHAType::SHA1(decode_hex(&info.sha1)?);
+
+        let file = cache.join(format!("{}.json", version));
+        let content = MetadataSetting::get_cached_or_fetch(&self.source, file, path, sha).await?;
+        let details: PistonVersion = serde_json::from_str(&content)?;
+        Ok(details.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::path::PathBuf;
+    use crate::utils::minecraft::arguments::{apply_placeholders, resolve, Features, LaunchPlaceholders};
+    use crate::utils::minecraft::auth::{Account, MinecraftAuthorizationFlow};
+    use crate::utils::minecraft::metadata::{Environment, Library};
+    use crate::utils::minecraft::piston::PistonMeta;
+    use crate::utils::minecraft::source::LocalSource;
+
+    fn fixtures() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join("piston")
+    }
+
+    fn cache() -> PathBuf {
+        env::temp_dir().join(format!("nolauncher-piston-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_package_details() {
+        let cache = cache();
+        let piston = PistonMeta::new(LocalSource::new(fixtures()));
+
+        let details = piston.package_details(&cache).await.unwrap();
+        let versions: Vec<&str> = details.versions.iter().map(|x| x.version.as_str()).collect();
+        assert_eq!(versions, ["1.21", "1.8.9"]);
+        assert!(details.versions[0].recommended);
+
+        // the cached manifest is used without the source
+        let piston = PistonMeta::new(LocalSource::new(env::temp_dir().join("not found")));
+        assert!(piston.manifest(&cache).await.is_ok());
+
+        tokio::fs::remove_dir_all(cache).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_version_details() {
+        let cache = cache();
+        let piston = PistonMeta::new(LocalSource::new(fixtures()));
+
+        let details = piston.version_details(&cache, "1.21").await.unwrap();
+        assert_eq!(details.uid, "net.minecraft");
+        assert_eq!(details.main_class.as_deref(), Some("net.minecraft.client.main.Main"));
+        assert_eq!(details.asset_index.unwrap().id, "17");
+        assert_eq!(details.compatible_java_majors, [21]);
+        let main_jar = details.main_jar.unwrap();
+        assert_eq!(main_jar.name, "com.mojang:minecraft:1.21:client");
+        assert!(main_jar.downloads.artifact.unwrap().url.ends_with("client.jar"));
+        assert!(details.minecraft_arguments.is_none());
+        let arguments = details.arguments.unwrap();
+        let game = resolve(&arguments.game, &Environment::host());
+        assert_eq!(game[..4], ["--username", "${auth_player_name}", "--version", "${version_name}"]);
+        assert!(!game.contains(&"--demo".to_string()));
+        let features = Features { is_demo_user: true, ..Default::default() };
+        let env = Environment { features, ..Environment::host() };
+        assert!(resolve(&arguments.game, &env).contains(&"--demo".to_string()));
+
+        let windows = Environment {
+            os: "windows".to_string(),
+            arch: "x86_64".to_string(),
+            ..Default::default()
+        };
+        let jvm = resolve(&arguments.jvm, &windows);
+        assert!(jvm[0].starts_with("-XX:HeapDumpPath="));
+        assert!(!jvm.contains(&"-XstartOnFirstThread".to_string()));
+        assert!(!jvm.contains(&"-Xss1M".to_string()));
+        assert!(jvm.contains(&"${classpath}".to_string()));
+        assert_eq!(details.libraries.len(), 2);
+
+        let details = piston.version_details(&cache, "1.8.9").await.unwrap();
+        assert!(details.arguments.is_none());
+        // the legacy arguments are converted by get_arguments
+        let game = resolve(&details.get_arguments().unwrap().game, &Environment::host());
+        assert!(game.contains(&"--userProperties".to_string()));
+        assert!(details.minecraft_arguments.unwrap().contains("--userProperties ${user_properties}"));
+        match &details.libraries[1] {
+            Library::Common(lib) => assert_eq!(lib.natives.get("linux").unwrap(), "natives-linux"),
+            _ => panic!("should be common library"),
+        }
+
+        assert!(piston.version_details(&cache, "0.0.0").await.is_err());
+
+        tokio::fs::remove_dir_all(cache).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_legacy_placeholders() {
+        let cache = cache();
+        let piston = PistonMeta::new(LocalSource::new(fixtures()));
+        let details = piston.version_details(&cache, "1.8.9").await.unwrap();
+
+        let flow = MinecraftAuthorizationFlow::new("client_id");
+        let auth = Account::offline("Steve").launch_auth(&flow).await.unwrap();
+        let mapping = LaunchPlaceholders {
+            auth: &auth,
+            client_id: "client_id",
+            version_name: &details.version,
+            version_type: "release",
+            classpath: "client.jar",
+            natives_directory: "natives",
+            game_directory: "game",
+            assets_root: "assets",
+            assets_index_name: &details.asset_index.as_ref().unwrap().id,
+        }
+        .mapping();
+
+        let game: Vec<String> = resolve(&details.get_arguments().unwrap().game, &Environment::host())
+            .iter()
+            .map(|x| apply_placeholders(x, &mapping))
+            .collect();
+        assert!(game.iter().all(|x| !x.contains("${")), "{:?}", game);
+        assert!(game.windows(2).any(|x| x == ["--version", "1.8.9"]));
+        assert!(game.windows(2).any(|x| x == ["--userProperties", "{}"]));
+
+        let session = apply_placeholders("${auth_session}", &mapping);
+        assert_eq!(session, format!("token:{}:{}", auth.access_token, auth.uuid));
+
+        tokio::fs::remove_dir_all(cache).await.unwrap();
+    }
+}
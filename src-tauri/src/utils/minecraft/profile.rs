@@ -0,0 +1,286 @@
+use reqwest::multipart::{Form, Part};
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::utils::minecraft::auth::{parse_json, Account, MinecraftAuthError, MinecraftAuthorizationFlow, MinecraftProfile, ResponseDetails};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The model of skin, `Classic` is Steve with 4px arms and `Slim` is Alex with 3px arms.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SkinVariant {
+    #[default]
+    Classic,
+    Slim,
+}
+
+impl SkinVariant {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkinVariant::Classic => "classic",
+            SkinVariant::Slim => "slim",
+        }
+    }
+}
+
+/// Check the file is a skin Minecraft accepts, which is a PNG of 64x64, or 64x32 in the old format.
+/// The size is read from the IHDR chunk, which is always the first chunk of PNG.
+pub fn check_skin(png: &[u8]) -> Result<(), MinecraftAuthError> {
+    if png.len() < 24 || !png.starts_with(PNG_SIGNATURE) || &png[12..16] != b"IHDR" {
+        return Err(MinecraftAuthError::InvalidSkin("The file is not a PNG image".to_string()));
+    }
+
+    let width = u32::from_be_bytes([png[16], png[17], png[18], png[19]]);
+    let height = u32::from_be_bytes([png[20], png[21], png[22], png[23]]);
+    if width != 64 || (height != 64 && height != 32) {
+        return Err(MinecraftAuthError::InvalidSkin(format!(
+            "The size of skin should be 64x64 or 64x32, but it is {width}x{height}"
+        )));
+    }
+
+    Ok(())
+}
+
+/// The rule of Minecraft name, 3-16 characters of a-z, A-Z, 0-9 and _.
+pub fn is_valid_name(name: &str) -> bool {
+    (3..=16).contains(&name.len()) && name.chars().all(|x| x.is_ascii_alphanumeric() || x == '_')
+}
+
+/// Whether a name can be used, the server also says `NOT_ALLOWED` for the names with bad words.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum NameAvailability {
+    Available,
+    Duplicate,
+    NotAllowed,
+}
+
+#[derive(Deserialize)]
+struct NameAvailabilityResponse {
+    status: NameAvailability,
+}
+
+/// The server responds 400 for the name breaking the rule, and 403 with the reason in `details.status`
+/// when the name can't be used, the 403 without reason means the name was changed in the last 30 days.
+fn name_change_error(name: &str, details: ResponseDetails) -> MinecraftAuthError {
+    let reason = serde_json::from_str::<serde_json::Value>(&details.body)
+        .ok()
+        .and_then(|x| x["details"]["status"].as_str().map(|x| x.to_string()));
+
+    match (details.status, reason.as_deref()) {
+        (_, Some("DUPLICATE")) => MinecraftAuthError::NameDuplicate(name.to_string()),
+        (Some(400), _) | (_, Some("NOT_ALLOWED")) => MinecraftAuthError::NameNotAllowed(name.to_string()),
+        (Some(403), _) => MinecraftAuthError::NameChangeCooldown(details),
+        _ => MinecraftAuthError::MinecraftServiceError(details),
+    }
+}
+
+/// The skin, cape and name APIs of Minecraft profile, most of them respond the updated profile,
+/// which replaces the stored one, so remember to save the [crate::utils::minecraft::auth::AccountList] after this.
+impl Account {
+    pub async fn upload_skin(
+        &mut self,
+        flow: &MinecraftAuthorizationFlow,
+        variant: SkinVariant,
+        png: Vec<u8>,
+    ) -> Result<(), MinecraftAuthError> {
+        check_skin(&png)?;
+
+        let file = Part::bytes(png)
+            .file_name("skin.png")
+            .mime_str("image/png")
+            .map_err(|e| MinecraftAuthError::InvalidSkin(e.to_string()))?;
+        let form = Form::new().text("variant", variant.as_str()).part("file", file);
+
+        let request = flow
+            .client()
+            .post(format!("{}/skins", flow.endpoints().minecraft_profile))
+            .multipart(form);
+        self.update_profile(flow, request).await
+    }
+
+    /// Let Minecraft services download the skin from `url`.
+    pub async fn set_skin_url(
+        &mut self,
+        flow: &MinecraftAuthorizationFlow,
+        variant: SkinVariant,
+        url: &str,
+    ) -> Result<(), MinecraftAuthError> {
+        let request = flow
+            .client()
+            .post(format!("{}/skins", flow.endpoints().minecraft_profile))
+            .json(&json!({
+                "variant": variant.as_str(),
+                "url": url
+            }));
+        self.update_profile(flow, request).await
+    }
+
+    /// Reset to the default skin.
+    pub async fn reset_skin(&mut self, flow: &MinecraftAuthorizationFlow) -> Result<(), MinecraftAuthError> {
+        let request = flow
+            .client()
+            .delete(format!("{}/skins/active", flow.endpoints().minecraft_profile));
+        self.update_profile(flow, request).await
+    }
+
+    /// Show the cape with `cape_id`, which should be one of the capes in profile.
+    pub async fn show_cape(&mut self, flow: &MinecraftAuthorizationFlow, cape_id: &str) -> Result<(), MinecraftAuthError> {
+        let request = flow
+            .client()
+            .put(format!("{}/capes/active", flow.endpoints().minecraft_profile))
+            .json(&json!({ "capeId": cape_id }));
+        self.update_profile(flow, request).await
+    }
+
+    pub async fn hide_cape(&mut self, flow: &MinecraftAuthorizationFlow) -> Result<(), MinecraftAuthError> {
+        let request = flow
+            .client()
+            .delete(format!("{}/capes/active", flow.endpoints().minecraft_profile));
+        self.update_profile(flow, request).await
+    }
+
+    /// Fetch the profile again, for the changes which are not made by us.
+    pub async fn refresh_profile(&mut self, flow: &MinecraftAuthorizationFlow) -> Result<(), MinecraftAuthError> {
+        let request = flow.client().get(&flow.endpoints().minecraft_profile);
+        self.update_profile(flow, request).await
+    }
+
+    /// Check whether `name` can be used by [Account::change_name], the name is checked locally first.
+    pub async fn check_name(
+        &mut self,
+        flow: &MinecraftAuthorizationFlow,
+        name: &str,
+    ) -> Result<NameAvailability, MinecraftAuthError> {
+        if !is_valid_name(name) {
+            return Ok(NameAvailability::NotAllowed);
+        }
+
+        let token = self.minecraft_token(flow).await?;
+        let response = flow
+            .client()
+            .get(format!("{}/name/{}/available", flow.endpoints().minecraft_profile, name))
+            .bearer_auth(token.access_token)
+            .send()
+            .await
+            .map_err(|e| MinecraftAuthError::RequestError(e.into()))?;
+
+        if response.status() != 200 {
+            return Err(MinecraftAuthError::MinecraftServiceError(
+                ResponseDetails::from_response(response).await,
+            ));
+        }
+
+        Ok(parse_json::<NameAvailabilityResponse>(response).await?.status)
+    }
+
+    pub async fn change_name(&mut self, flow: &MinecraftAuthorizationFlow, name: &str) -> Result<(), MinecraftAuthError> {
+        if !is_valid_name(name) {
+            return Err(MinecraftAuthError::NameNotAllowed(name.to_string()));
+        }
+
+        let request = flow
+            .client()
+            .put(format!("{}/name/{}", flow.endpoints().minecraft_profile, name));
+        self.update_profile_with(flow, request, |details| name_change_error(name, details))
+            .await
+    }
+
+    async fn update_profile(
+        &mut self,
+        flow: &MinecraftAuthorizationFlow,
+        request: RequestBuilder,
+    ) -> Result<(), MinecraftAuthError> {
+        self.update_profile_with(flow, request, MinecraftAuthError::MinecraftServiceError)
+            .await
+    }
+
+    /// Same as [Account::update_profile], but the failed response is turned into error by `on_error`.
+    async fn update_profile_with(
+        &mut self,
+        flow: &MinecraftAuthorizationFlow,
+        request: RequestBuilder,
+        on_error: impl FnOnce(ResponseDetails) -> MinecraftAuthError,
+    ) -> Result<(), MinecraftAuthError> {
+        let token = self.minecraft_token(flow).await?;
+        let response = request.bearer_auth(token.access_token).send().await;
+
+        let profile = match response {
+            Ok(response) => {
+                if response.status() == 200 {
+                    parse_json::<MinecraftProfile>(response).await?
+                } else {
+                    return Err(on_error(ResponseDetails::from_response(response).await));
+                }
+            }
+            Err(e) => return Err(MinecraftAuthError::RequestError(e.into())),
+        };
+
+        self.profile = profile;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::minecraft::auth::{MinecraftAuthError, ResponseDetails};
+    use crate::utils::minecraft::profile::{check_skin, is_valid_name, name_change_error, NameAvailability, SkinVariant};
+
+    /// The beginning of a PNG, which is enough for [check_skin].
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        png.extend_from_slice(&width.to_be_bytes());
+        png.extend_from_slice(&height.to_be_bytes());
+        png.extend_from_slice(&[8, 6, 0, 0, 0]);
+        png
+    }
+
+    #[test]
+    fn test_check_skin() {
+        assert!(check_skin(&png_header(64, 64)).is_ok());
+        assert!(check_skin(&png_header(64, 32)).is_ok());
+        assert!(matches!(check_skin(&png_header(128, 128)), Err(MinecraftAuthError::InvalidSkin(_))));
+        assert!(matches!(check_skin(b"GIF89a"), Err(MinecraftAuthError::InvalidSkin(_))));
+    }
+
+    #[test]
+    fn test_skin_variant() {
+        assert_eq!(serde_json::to_string(&SkinVariant::Slim).unwrap(), "\"slim\"");
+        assert_eq!(serde_json::from_str::<SkinVariant>("\"classic\"").unwrap(), SkinVariant::Classic);
+    }
+
+    #[test]
+    fn test_name() {
+        assert!(is_valid_name("Notch_123"));
+        assert!(!is_valid_name("ab"));
+        assert!(!is_valid_name("a-b-c"));
+        assert!(!is_valid_name("abcdefghijklmnopq"));
+        assert_eq!(
+            serde_json::from_str::<NameAvailability>("\"NOT_ALLOWED\"").unwrap(),
+            NameAvailability::NotAllowed
+        );
+    }
+
+    #[test]
+    fn test_name_change_error() {
+        let details = |status: u16, body: &str| ResponseDetails {
+            status: Some(status),
+            description: None,
+            body: body.to_string(),
+        };
+
+        let duplicate = details(403, r#"{"path":"/minecraft/profile/name/Notch","details":{"status":"DUPLICATE"}}"#);
+        assert!(matches!(name_change_error("Notch", duplicate), MinecraftAuthError::NameDuplicate(_)));
+
+        let not_allowed = details(403, r#"{"details":{"status":"NOT_ALLOWED"}}"#);
+        assert!(matches!(name_change_error("Notch", not_allowed), MinecraftAuthError::NameNotAllowed(_)));
+        assert!(matches!(name_change_error("Notch", details(400, "")), MinecraftAuthError::NameNotAllowed(_)));
+
+        let cooldown = details(403, r#"{"errorMessage":"Forbidden"}"#);
+        assert!(matches!(name_change_error("Notch", cooldown), MinecraftAuthError::NameChangeCooldown(_)));
+
+        let other = details(429, "");
+        assert!(matches!(name_change_error("Notch", other), MinecraftAuthError::MinecraftServiceError(_)));
+    }
+}
@@ -0,0 +1,283 @@
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Result};
+use log::warn;
+use serde::Deserialize;
+use tauri::AppHandle;
+use crate::constant::SKIN_CACHE;
+use crate::utils::minecraft::auth::MinecraftSkin;
+
+/// The avatar rendered from skin, `Face` is the front of head only,
+/// and `Head` has the hat (overlay) layer on it, which is what the game shows.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AvatarKind {
+    Face,
+    Head,
+}
+
+impl AvatarKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AvatarKind::Face => "face",
+            AvatarKind::Head => "head",
+        }
+    }
+}
+
+/// A decoded skin texture in RGBA8.
+pub struct SkinTexture {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<u8>,
+}
+
+impl SkinTexture {
+    /// Decode the PNG, and make sure it is 64x64 or 64x32 in the old format.
+    pub fn decode(png: &[u8]) -> Result<Self> {
+        let mut decoder = png::Decoder::new(png);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+
+        if info.width != 64 || (info.height != 64 && info.height != 32) {
+            bail!("The size of skin should be 64x64 or 64x32, but it is {}x{}", info.width, info.height);
+        }
+
+        let buffer = &buffer[..info.buffer_size()];
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buffer.to_vec(),
+            png::ColorType::Rgb => buffer.chunks(3).flat_map(|x| [x[0], x[1], x[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buffer.chunks(2).flat_map(|x| [x[0], x[0], x[0], x[1]]).collect(),
+            png::ColorType::Grayscale => buffer.iter().flat_map(|x| [*x, *x, *x, 255]).collect(),
+            png::ColorType::Indexed => bail!("The palette of skin is not expanded"),
+        };
+
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    /// The old 64x32 skins often fill the hat with an opaque color, the game ignores the hat of them
+    /// when it has no transparent pixel, so do we.
+    fn has_hat(&self) -> bool {
+        if self.height == 64 {
+            return true;
+        }
+        (40..48).any(|x| (8..16).any(|y| self.pixel(x, y)[3] < 128))
+    }
+
+    /// Render the front of head to a `size`x`size` PNG, scaled by nearest neighbor to keep it pixelated.
+    pub fn render(&self, kind: AvatarKind, size: u32) -> Result<Vec<u8>> {
+        let overlay = kind == AvatarKind::Head && self.has_hat();
+
+        let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+        for y in 0..size {
+            for x in 0..size {
+                let (u, v) = (x * 8 / size, y * 8 / size);
+                // the face is always opaque in game
+                let [r, g, b, _] = self.pixel(8 + u, 8 + v);
+                let mut color = [r, g, b, 255];
+                if overlay {
+                    color = blend(color, self.pixel(40 + u, 8 + v));
+                }
+                pixels.extend_from_slice(&color);
+            }
+        }
+
+        encode(size, &pixels)
+    }
+}
+
+/// Draw `top` over the opaque `bottom`.
+fn blend(bottom: [u8; 4], top: [u8; 4]) -> [u8; 4] {
+    let alpha = top[3] as u32;
+    let mix = |b: u8, t: u8| ((t as u32 * alpha + b as u32 * (255 - alpha)) / 255) as u8;
+    [mix(bottom[0], top[0]), mix(bottom[1], top[1]), mix(bottom[2], top[2]), 255]
+}
+
+fn encode(size: u32, pixels: &[u8]) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png, size, size);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(pixels)?;
+        writer.finish()?;
+    }
+    Ok(png)
+}
+
+/// The skin textures downloaded before, keyed by `texture_key`, so the skins can be shown without network.
+pub struct SkinCache {
+    folder: PathBuf,
+}
+
+impl SkinCache {
+    pub fn new(folder: &Path) -> Self {
+        Self {
+            folder: folder.to_path_buf(),
+        }
+    }
+
+    pub fn from_app(app: &AppHandle) -> Result<Self> {
+        Ok(Self::new(&SKIN_CACHE.to_path(app)?))
+    }
+
+    /// The file of skin in cache, `texture_key` comes from the profile, so it is checked before it is
+    /// used as the file name, a key like `../` could write out of the cache.
+    fn cache_file(&self, skin: &MinecraftSkin, suffix: &str) -> Result<PathBuf> {
+        let key = &skin.texture_key;
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+            bail!("The texture key {:?} of skin is invalid", key);
+        }
+        Ok(self.folder.join(format!("{}{}.png", key, suffix)))
+    }
+
+    /// Get the texture of skin, it is only downloaded when it is not in the cache.
+    pub async fn texture(&self, skin: &MinecraftSkin) -> Result<Vec<u8>> {
+        let file = self.cache_file(skin, "")?;
+
+        if let Ok(content) = tokio::fs::read(&file).await {
+            if SkinTexture::decode(&content).is_ok() {
+                return Ok(content);
+            }
+            warn!("The cached skin {} is broken, download it again", skin.texture_key);
+        }
+
+        let content = reqwest::get(&skin.url)
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec();
+        SkinTexture::decode(&content).map_err(|e| anyhow!("The skin {} is invalid: {}", skin.url, e))?;
+
+        tokio::fs::create_dir_all(&self.folder).await?;
+        tokio::fs::write(&file, &content).await?;
+        Ok(content)
+    }
+
+    /// Get the avatar rendered from skin, the result is cached too.
+    pub async fn avatar(&self, skin: &MinecraftSkin, kind: AvatarKind, size: u32) -> Result<Vec<u8>> {
+        if size == 0 || size > 512 {
+            bail!("The size of avatar should be 1 to 512, but it is {}", size);
+        }
+
+        let file = self.cache_file(skin, &format!("-{}-{}", kind.as_str(), size))?;
+        if let Ok(content) = tokio::fs::read(&file).await {
+            return Ok(content);
+        }
+
+        let texture = self.texture(skin).await?;
+        let avatar = SkinTexture::decode(&texture)?.render(kind, size)?;
+        tokio::fs::write(&file, &avatar).await?;
+        Ok(avatar)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use crate::utils::minecraft::auth::MinecraftSkin;
+    use crate::utils::minecraft::skin::{encode, AvatarKind, SkinCache, SkinTexture};
+
+    /// A skin which face is red, and the hat is blue with `hat_alpha`,
+    /// there is a transparent hole at (0, 0) of hat unless the hat is opaque.
+    fn skin(height: u32, hat_alpha: u8) -> Vec<u8> {
+        let mut pixels = vec![0u8; (64 * height * 4) as usize];
+        let mut set = |x: u32, y: u32, color: [u8; 4]| {
+            let i = ((y * 64 + x) * 4) as usize;
+            pixels[i..i + 4].copy_from_slice(&color);
+        };
+        for y in 8..16 {
+            for x in 8..16 {
+                set(x, y, [255, 0, 0, 255]);
+                set(x + 32, y, [0, 0, 255, hat_alpha]);
+            }
+        }
+        if hat_alpha != 255 {
+            set(40, 8, [0, 0, 0, 0]);
+        }
+
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 64, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(&pixels).unwrap();
+        png
+    }
+
+    fn pixel(png: &[u8], x: u32, y: u32) -> [u8; 4] {
+        let texture = png::Decoder::new(png);
+        let mut reader = texture.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        let i = ((y * info.width + x) * 4) as usize;
+        [buffer[i], buffer[i + 1], buffer[i + 2], buffer[i + 3]]
+    }
+
+    #[test]
+    fn test_render() {
+        let texture = SkinTexture::decode(&skin(64, 128)).unwrap();
+
+        let face = texture.render(AvatarKind::Face, 16).unwrap();
+        assert_eq!(pixel(&face, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&face, 15, 15), [255, 0, 0, 255]);
+
+        let head = texture.render(AvatarKind::Head, 16).unwrap();
+        assert_eq!(pixel(&head, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&head, 1, 1), [255, 0, 0, 255]);
+        assert_eq!(pixel(&head, 2, 0), [127, 0, 128, 255]);
+    }
+
+    #[test]
+    fn test_legacy_hat() {
+        // the opaque hat of old skin is ignored
+        let texture = SkinTexture::decode(&skin(32, 255)).unwrap();
+        let head = texture.render(AvatarKind::Head, 8).unwrap();
+        assert_eq!(pixel(&head, 1, 0), [255, 0, 0, 255]);
+
+        let texture = SkinTexture::decode(&skin(64, 255)).unwrap();
+        let head = texture.render(AvatarKind::Head, 8).unwrap();
+        assert_eq!(pixel(&head, 1, 0), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_invalid_size() {
+        let png = encode(32, &vec![0; 32 * 32 * 4]).unwrap();
+        assert!(SkinTexture::decode(&png).is_err());
+        assert!(SkinTexture::decode(b"not png").is_err());
+    }
+
+    fn skin_with_key(key: &str) -> MinecraftSkin {
+        MinecraftSkin {
+            id: "id".to_string(),
+            state: "ACTIVE".to_string(),
+            url: "http://127.0.0.1:1/skin.png".to_string(),
+            texture_key: key.to_string(),
+            variant: "CLASSIC".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_texture_key() {
+        let cache = SkinCache::new(&env::temp_dir().join("nolauncher-skins"));
+        for key in ["../../evil", "", "a/b", "a.b"] {
+            let skin = skin_with_key(key);
+            assert!(cache.texture(&skin).await.is_err());
+            assert!(cache.avatar(&skin, AvatarKind::Head, 8).await.is_err());
+        }
+
+        let skin = skin_with_key("3b60a1f6d562f52aaebbf1434f1de147933a3affe0e764fa49ea057536623cd3");
+        assert!(cache.cache_file(&skin, "").is_ok());
+    }
+}
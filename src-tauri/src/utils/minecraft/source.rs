@@ -0,0 +1,135 @@
+use std::future::Future;
+use std::path::PathBuf;
+use reqwest::{Client, Url};
+use crate::utils::minecraft::metadata::MetadataFileError;
+
+/// Where the metadata files are read from, `path` is relative to the root of metadata repository,
+/// like `index.json`, `net.minecraft/index.json` or `net.minecraft/1.21.json`.
+pub trait MetadataSource {
+    fn fetch(&self, path: &str) -> impl Future<Output = Result<String, MetadataFileError>> + Send;
+}
+
+/// The repository served by HTTP, like `https://meta.prismlauncher.org/v1/`.
+#[derive(Debug, Clone)]
+pub struct HttpSource {
+    root: String,
+    client: Client,
+}
+
+impl HttpSource {
+    pub fn new(root: &str) -> Self {
+        Self {
+            root: root.trim_end_matches('/').to_string(),
+            client: Client::new(),
+        }
+    }
+}
+
+impl MetadataSource for HttpSource {
+    async fn fetch(&self, path: &str) -> Result<String, MetadataFileError> {
+        let response = self
+            .client
+            .get(format!("{}/{}", self.root, path))
+            .send()
+            .await
+            .map_err(|_| MetadataFileError::Fetching)?;
+
+        if !response.status().is_success() {
+            return Err(MetadataFileError::Fetching);
+        }
+
+        response.text().await.map_err(|e| MetadataFileError::Unknown(e.to_string()))
+    }
+}
+
+/// The repository mirrored to a local directory, which has the same layout as the HTTP one,
+/// so the launcher can work without network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalSource {
+    root: PathBuf,
+}
+
+impl LocalSource {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+impl MetadataSource for LocalSource {
+    async fn fetch(&self, path: &str) -> Result<String, MetadataFileError> {
+        tokio::fs::read_to_string(self.root.join(path))
+            .await
+            .map_err(|e| MetadataFileError::IO(e.kind()))
+    }
+}
+
+/// The source chosen by the url of repository, `file://` url is read by [LocalSource],
+/// and the others are by [HttpSource].
+#[derive(Debug, Clone)]
+pub enum Source {
+    Http(HttpSource),
+    Local(LocalSource),
+}
+
+impl Source {
+    pub fn from_url(url: &str) -> Self {
+        match Url::parse(url).ok().filter(|x| x.scheme() == "file").and_then(|x| x.to_file_path().ok()) {
+            Some(path) => Source::Local(LocalSource::new(path)),
+            None => Source::Http(HttpSource::new(url)),
+        }
+    }
+}
+
+impl MetadataSource for Source {
+    async fn fetch(&self, path: &str) -> Result<String, MetadataFileError> {
+        match self {
+            Source::Http(source) => source.fetch(path).await,
+            Source::Local(source) => source.fetch(path).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::ErrorKind;
+    use std::path::PathBuf;
+    use reqwest::Url;
+    use crate::utils::minecraft::metadata::MetadataFileError;
+    use crate::utils::minecraft::source::{HttpSource, LocalSource, MetadataSource, Source};
+
+    fn fixtures() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join("metadata")
+    }
+
+    #[test]
+    fn test_from_url() {
+        let url = Url::from_directory_path(fixtures()).unwrap();
+        match Source::from_url(url.as_str()) {
+            Source::Local(source) => assert_eq!(source, LocalSource::new(fixtures())),
+            _ => panic!("should be LocalSource"),
+        }
+
+        match Source::from_url("https://meta.prismlauncher.org/v1/") {
+            Source::Http(source) => assert_eq!(source.root, "https://meta.prismlauncher.org/v1"),
+            _ => panic!("should be HttpSource"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_source() {
+        let source = LocalSource::new(fixtures());
+
+        let content = source.fetch("org.lwjgl/index.json").await.unwrap();
+        assert!(content.contains("\"uid\": \"org.lwjgl\""));
+
+        let result = source.fetch("org.lwjgl/0.0.0.json").await;
+        assert_eq!(result, Err(MetadataFileError::IO(ErrorKind::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_http_source_error() {
+        // nothing listens on the port
+        let source = HttpSource::new("http://127.0.0.1:1/v1/");
+        assert_eq!(source.fetch("index.json").await, Err(MetadataFileError::Fetching));
+    }
+}
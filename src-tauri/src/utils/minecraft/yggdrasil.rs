@@ -0,0 +1,504 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use log::warn;
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tauri::AppHandle;
+use thiserror::Error;
+use crate::constant::AUTHLIB_INJECTOR_PATH;
+
+const AUTHLIB_INJECTOR_LATEST: &str = "https://authlib-injector.yushi.moe/artifact/latest.json";
+/// The header of authlib-injector API Location Indication (ALI), which tell us where the real API root is.
+const API_LOCATION_HEADER: &str = "x-authlib-injector-api-location";
+
+#[derive(Error, Debug)]
+pub enum YggdrasilError {
+    #[error("Failed to connect to the authentication server. details:{0}")]
+    Request(String),
+    #[error("The authentication server return error: {error}, details:{message}")]
+    Server { status: u16, error: String, message: String },
+    #[error("The access token is invalid, please login again.")]
+    InvalidToken,
+    #[error("There is no available profile in this account.")]
+    NoProfile,
+}
+
+impl YggdrasilError {
+    /// Whether the server refused the token or the password, the other errors of server may be temporary.
+    pub fn is_forbidden(&self) -> bool {
+        match self {
+            YggdrasilError::InvalidToken => true,
+            YggdrasilError::Server { status, error, .. } => {
+                matches!(status, 401 | 403) || error == "ForbiddenOperationException"
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct YggdrasilProfile {
+    pub id: String,
+    pub name: String,
+}
+
+/// The response of authenticate and refresh.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YggdrasilAuthResponse {
+    pub access_token: String,
+    pub client_token: String,
+    #[serde(default)]
+    pub available_profiles: Vec<YggdrasilProfile>,
+    pub selected_profile: Option<YggdrasilProfile>,
+}
+
+/// The login session of an account on Yggdrasil server, which is stored in [crate::utils::minecraft::auth::Account].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct YggdrasilSession {
+    pub api_root: String,
+    pub access_token: String,
+    pub client_token: String,
+}
+
+/// A Yggdrasil-compatible authentication server, see
+/// https://github.com/yushijinhun/authlib-injector/wiki/Yggdrasil-%E6%9C%8D%E5%8A%A1%E7%AB%AF%E6%8A%80%E6%9C%AF%E8%A7%84%E8%8C%83
+pub struct YggdrasilServer {
+    client: Client,
+    pub api_root: String,
+}
+
+impl YggdrasilServer {
+    pub fn new(api_root: &str) -> Self {
+        Self {
+            client: Client::new(),
+            api_root: api_root.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Find the API root from the url user entered, the server may tell us the real API root by ALI header.
+    pub async fn resolve(url: &str) -> Result<Self, YggdrasilError> {
+        let url = if url.starts_with("http://") || url.starts_with("https://") {
+            url.to_string()
+        } else {
+            format!("https://{url}")
+        };
+
+        let client = Client::new();
+        let response = client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| YggdrasilError::Request(e.to_string()))?;
+
+        let api_root = match response.headers().get(API_LOCATION_HEADER).and_then(|x| x.to_str().ok()) {
+            Some(location) => Url::parse(&url)
+                .and_then(|x| x.join(location))
+                .map(|x| x.to_string())
+                .unwrap_or(url),
+            None => url,
+        };
+
+        Ok(Self {
+            client,
+            api_root: api_root.trim_end_matches('/').to_string(),
+        })
+    }
+
+    async fn post(&self, path: &str, body: Value) -> Result<reqwest::Response, YggdrasilError> {
+        let response = self
+            .client
+            .post(format!("{}/authserver/{}", self.api_root, path))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| YggdrasilError::Request(e.to_string()))?;
+
+        if response.status().is_success() {
+            return Ok(response);
+        }
+
+        let status = response.status().as_u16();
+        let value = response.json::<Value>().await.unwrap_or_default();
+        Err(YggdrasilError::Server {
+            status,
+            error: value["error"].as_str().unwrap_or("Unknown Error").to_string(),
+            message: value["errorMessage"].as_str().unwrap_or_default().to_string(),
+        })
+    }
+
+    pub async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+        client_token: &str,
+    ) -> Result<YggdrasilAuthResponse, YggdrasilError> {
+        let response = self
+            .post(
+                "authenticate",
+                json!({
+                    "agent": {
+                        "name": "Minecraft",
+                        "version": 1
+                    },
+                    "username": username,
+                    "password": password,
+                    "clientToken": client_token,
+                    "requestUser": false
+                }),
+            )
+            .await?;
+
+        response
+            .json()
+            .await
+            .map_err(|e| YggdrasilError::Request(e.to_string()))
+    }
+
+    /// Login and bind the token to a profile, the first available profile is selected
+    /// when the server doesn't select one for us.
+    pub async fn login(&self, username: &str, password: &str) -> Result<(YggdrasilSession, YggdrasilProfile), YggdrasilError> {
+        let client_token = uuid::Uuid::new_v4().simple().to_string();
+        let response = self.authenticate(username, password, &client_token).await?;
+
+        let session = YggdrasilSession {
+            api_root: self.api_root.clone(),
+            access_token: response.access_token,
+            client_token: response.client_token,
+        };
+
+        if let Some(profile) = response.selected_profile {
+            return Ok((session, profile));
+        }
+
+        // the token without selected profile can't join server, so it is bound by refresh
+        let profile = response.available_profiles.first().cloned().ok_or(YggdrasilError::NoProfile)?;
+        let response = self.refresh(&session, Some(&profile)).await?;
+        let profile = response.selected_profile.unwrap_or(profile);
+
+        Ok((
+            YggdrasilSession {
+                access_token: response.access_token,
+                client_token: response.client_token,
+                ..session
+            },
+            profile,
+        ))
+    }
+
+    /// Refresh the token, it is bound to `selected_profile` when it is given.
+    pub async fn refresh(
+        &self,
+        session: &YggdrasilSession,
+        selected_profile: Option<&YggdrasilProfile>,
+    ) -> Result<YggdrasilAuthResponse, YggdrasilError> {
+        let mut body = json!({
+            "accessToken": session.access_token,
+            "clientToken": session.client_token,
+            "requestUser": false
+        });
+        if let Some(profile) = selected_profile {
+            body["selectedProfile"] = json!(profile);
+        }
+
+        let response = self.post("refresh", body).await?;
+
+        response
+            .json()
+            .await
+            .map_err(|e| YggdrasilError::Request(e.to_string()))
+    }
+
+    /// Return false when the server says the token is invalid.
+    pub async fn validate(&self, session: &YggdrasilSession) -> Result<bool, YggdrasilError> {
+        let response = self
+            .post(
+                "validate",
+                json!({
+                    "accessToken": session.access_token,
+                    "clientToken": session.client_token
+                }),
+            )
+            .await;
+
+        match response {
+            Ok(_) => Ok(true),
+            Err(YggdrasilError::Server { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn invalidate(&self, session: &YggdrasilSession) -> Result<(), YggdrasilError> {
+        self.post(
+            "invalidate",
+            json!({
+                "accessToken": session.access_token,
+                "clientToken": session.client_token
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Invalidate all the tokens of the user by the password.
+    pub async fn signout(&self, username: &str, password: &str) -> Result<(), YggdrasilError> {
+        self.post(
+            "signout",
+            json!({
+                "username": username,
+                "password": password
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// The metadata of the server, it is passed to authlib-injector to save a request when game starting.
+    pub async fn metadata(&self) -> Result<String, YggdrasilError> {
+        let response = self
+            .client
+            .get(&self.api_root)
+            .send()
+            .await
+            .map_err(|e| YggdrasilError::Request(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(YggdrasilError::Request(format!("status code:{}", response.status().as_u16())));
+        }
+
+        response
+            .text()
+            .await
+            .map_err(|e| YggdrasilError::Request(e.to_string()))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthlibInjectorArtifact {
+    version: String,
+    download_url: String,
+    checksums: HashMap<String, String>,
+}
+
+/// Download the latest authlib-injector if we don't have it, return the path of the jar.
+async fn download_authlib_injector(folder: &Path) -> Result<PathBuf> {
+    let artifact = reqwest::get(AUTHLIB_INJECTOR_LATEST)
+        .await?
+        .error_for_status()?
+        .json::<AuthlibInjectorArtifact>()
+        .await?;
+
+    let sha256 = artifact
+        .checksums
+        .get("sha256")
+        .ok_or(anyhow!("authlib-injector has no sha256 checksum"))?;
+    let file = folder.join(format!("authlib-injector-{}.jar", artifact.version));
+
+    if let Ok(content) = tokio::fs::read(&file).await {
+        if format!("{:x}", Sha256::digest(&content)) == *sha256 {
+            return Ok(file);
+        }
+    }
+
+    let content = reqwest::get(&artifact.download_url)
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    if format!("{:x}", Sha256::digest(&content)) != *sha256 {
+        return Err(anyhow!("The checksum of authlib-injector {} is not matched", artifact.version));
+    }
+
+    tokio::fs::write(&file, content).await?;
+    Ok(file)
+}
+
+/// Make sure authlib-injector is ready, we fall back to the one downloaded before when we can't fetch the latest.
+pub async fn prepare_authlib_injector(app: &AppHandle) -> Result<PathBuf> {
+    let folder = AUTHLIB_INJECTOR_PATH.to_path(app)?;
+    tokio::fs::create_dir_all(&folder).await?;
+
+    match download_authlib_injector(&folder).await {
+        Ok(file) => Ok(file),
+        Err(e) => {
+            warn!("Failed to fetch the latest authlib-injector: {}", e);
+
+            let mut jars = std::fs::read_dir(&folder)?
+                .filter_map(|x| x.ok())
+                .map(|x| x.path())
+                .filter(|x| x.extension().map(|x| x == "jar").unwrap_or(false))
+                .collect::<Vec<_>>();
+            jars.sort_by_key(|x| jar_version(x));
+
+            jars.pop().ok_or(anyhow!("authlib-injector is not downloaded: {}", e))
+        }
+    }
+}
+
+/// The version of `authlib-injector-{version}.jar` as numbers, so `1.2.10` is newer than `1.2.9`.
+fn jar_version(jar: &Path) -> Vec<u64> {
+    jar.file_stem()
+        .and_then(|x| x.to_str())
+        .and_then(|x| x.strip_prefix("authlib-injector-"))
+        .map(|x| x.split('.').map(|x| x.parse().unwrap_or(0)).collect())
+        .unwrap_or_default()
+}
+
+/// The JVM arguments to launch game with authlib-injector, they should be placed before other JVM arguments.
+pub async fn authlib_injector_args(app: &AppHandle, api_root: &str) -> Result<Vec<String>> {
+    let jar = prepare_authlib_injector(app).await?;
+    let mut args = vec![format!("-javaagent:{}={}", jar.display(), api_root)];
+
+    match YggdrasilServer::new(api_root).metadata().await {
+        Ok(metadata) => args.push(format!(
+            "-Dauthlibinjector.yggdrasil.prefetched={}",
+            STANDARD.encode(metadata)
+        )),
+        Err(e) => warn!("Failed to prefetch the metadata of {}: {}", api_root, e),
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use serde_json::json;
+    use crate::utils::minecraft::auth::test::mock_server;
+    use crate::utils::minecraft::yggdrasil::{jar_version, YggdrasilError, YggdrasilServer, YggdrasilSession};
+
+    fn profile_json(id: &str, name: &str) -> serde_json::Value {
+        json!({ "id": id, "name": name })
+    }
+
+    fn session(server: &str) -> YggdrasilSession {
+        YggdrasilSession {
+            api_root: server.to_string(),
+            access_token: "access_token".to_string(),
+            client_token: "client_token".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_selected_profile() {
+        let body = json!({
+            "accessToken": "access_token",
+            "clientToken": "client_token",
+            "availableProfiles": [profile_json("1", "Alex"), profile_json("2", "Steve")],
+            "selectedProfile": profile_json("2", "Steve")
+        });
+        let server = mock_server(vec![("/authserver/authenticate", 200, body.to_string())]).await;
+
+        let (session, profile) = YggdrasilServer::new(&server).login("user", "password").await.unwrap();
+        assert_eq!(profile.name, "Steve");
+        assert_eq!(session.access_token, "access_token");
+        assert_eq!(session.api_root, server);
+    }
+
+    #[tokio::test]
+    async fn test_login_bind_profile() {
+        let authenticate = json!({
+            "accessToken": "unbound_token",
+            "clientToken": "client_token",
+            "availableProfiles": [profile_json("1", "Alex"), profile_json("2", "Steve")]
+        });
+        let refresh = json!({
+            "accessToken": "bound_token",
+            "clientToken": "client_token",
+            "selectedProfile": profile_json("1", "Alex")
+        });
+        let server = mock_server(vec![
+            ("/authserver/authenticate", 200, authenticate.to_string()),
+            ("/authserver/refresh", 200, refresh.to_string()),
+        ])
+        .await;
+
+        let (session, profile) = YggdrasilServer::new(&server).login("user", "password").await.unwrap();
+        assert_eq!(profile.name, "Alex");
+        assert_eq!(session.access_token, "bound_token");
+
+        let body = json!({ "accessToken": "access_token", "clientToken": "client_token", "availableProfiles": [] });
+        let server = mock_server(vec![("/authserver/authenticate", 200, body.to_string())]).await;
+        let result = YggdrasilServer::new(&server).login("user", "password").await;
+        assert!(matches!(result, Err(YggdrasilError::NoProfile)));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_error() {
+        let body = json!({ "error": "ForbiddenOperationException", "errorMessage": "Invalid credentials." });
+        let server = mock_server(vec![("/authserver/authenticate", 403, body.to_string())]).await;
+
+        match YggdrasilServer::new(&server).login("user", "wrong").await {
+            Err(YggdrasilError::Server { status, error, message }) => {
+                assert_eq!(status, 403);
+                assert_eq!(error, "ForbiddenOperationException");
+                assert_eq!(message, "Invalid credentials.");
+            }
+            other => panic!("should be server error: {:?}", other.map(|x| x.1)),
+        }
+    }
+
+    #[test]
+    fn test_is_forbidden() {
+        let server = |status: u16, error: &str| YggdrasilError::Server {
+            status,
+            error: error.to_string(),
+            message: String::new(),
+        };
+
+        assert!(server(403, "ForbiddenOperationException").is_forbidden());
+        assert!(server(401, "Unauthorized").is_forbidden());
+        assert!(!server(500, "Unknown Error").is_forbidden());
+        assert!(!server(429, "TooManyRequestsException").is_forbidden());
+        assert!(YggdrasilError::InvalidToken.is_forbidden());
+        assert!(!YggdrasilError::Request("timeout".to_string()).is_forbidden());
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let server = mock_server(vec![("/authserver/validate", 204, String::new())]).await;
+        assert!(YggdrasilServer::new(&server).validate(&session(&server)).await.unwrap());
+
+        let body = json!({ "error": "ForbiddenOperationException", "errorMessage": "Invalid token." });
+        let server = mock_server(vec![("/authserver/validate", 403, body.to_string())]).await;
+        assert!(!YggdrasilServer::new(&server).validate(&session(&server)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_signout() {
+        let server = mock_server(vec![("/authserver/signout", 204, String::new())]).await;
+        YggdrasilServer::new(&server).signout("user", "password").await.unwrap();
+
+        let body = json!({ "error": "ForbiddenOperationException", "errorMessage": "Invalid credentials." });
+        let server = mock_server(vec![("/authserver/signout", 403, body.to_string())]).await;
+        let result = YggdrasilServer::new(&server).signout("user", "wrong").await;
+        assert!(matches!(result, Err(YggdrasilError::Server { status: 403, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_metadata() {
+        let server = mock_server(vec![("/", 200, json!({ "meta": {} }).to_string())]).await;
+        assert!(YggdrasilServer::new(&server).metadata().await.unwrap().contains("meta"));
+
+        let server = mock_server(vec![]).await;
+        assert!(YggdrasilServer::new(&server).metadata().await.is_err());
+    }
+
+    #[test]
+    fn test_jar_version() {
+        let mut jars = vec![
+            PathBuf::from("authlib-injector-1.2.10.jar"),
+            PathBuf::from("authlib-injector-1.2.9.jar"),
+            PathBuf::from("authlib-injector-1.1.47.jar"),
+        ];
+        jars.sort_by_key(|x| jar_version(x));
+        assert_eq!(jars.pop().unwrap(), PathBuf::from("authlib-injector-1.2.10.jar"));
+        assert_eq!(jars.pop().unwrap(), PathBuf::from("authlib-injector-1.2.9.jar"));
+    }
+}
@@ -27,13 +27,17 @@
 
 use std::io::Error;
 use serde::Serialize;
+use crate::utils::minecraft::auth::{MinecraftAuthError, ResponseDetails};
 
 // Just extending the `anyhow::Error`
 #[derive(Debug,Serialize)]
 pub struct CommandError{
     pub status:String,
     #[serde(serialize_with="serialize")]
-    pub error:anyhow::Error
+    pub error:anyhow::Error,
+    /// The response of remote server which cause this error, so the frontend can show why it is failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details:Option<ResponseDetails>
 }
 
 impl std::error::Error for CommandError {}
@@ -55,7 +59,8 @@ impl From<anyhow::Error> for CommandError {
     fn from(error: anyhow::Error) -> Self {
         Self {
             status: "error".to_string(),
-            error
+            error,
+            details: None
         }
     }
 }
@@ -64,6 +69,17 @@ impl From<std::io::Error> for CommandError{
     fn from(value: Error) -> Self {
         Self {
             status: "error".to_string(),
+            error: value.into(),
+            details: None
+        }
+    }
+}
+
+impl From<MinecraftAuthError> for CommandError {
+    fn from(value: MinecraftAuthError) -> Self {
+        Self {
+            status: "error".to_string(),
+            details: value.details().cloned(),
             error: value.into()
         }
     }
@@ -73,7 +89,8 @@ impl From<tauri::Error> for CommandError {
     fn from(value: tauri::Error) -> Self {
         Self {
             status: "error".to_string(),
-            error: value.into()
+            error: value.into(),
+            details: None
         }
     }
 }
@@ -114,7 +131,8 @@ impl<T, E> IntoTAResult<T> for std::result::Result<T, E>
     fn into_ta_result(self) -> CommandResult<T> {
         self.map_err(|e| CommandError{
             status: "error".to_string(),
-            error:e.into()
+            error:e.into(),
+            details: None
         })
     }
 }
@@ -134,7 +152,8 @@ impl<T> IntoTAResult<T> for anyhow::Error {
     fn into_ta_result(self) -> CommandResult<T> {
         Err(CommandError{
             status:"error".to_string(),
-            error:self
+            error:self,
+            details: None
         })
     }
 }
@@ -155,7 +174,8 @@ impl IntoEmptyTAResult<()> for anyhow::Error {
     fn into_ta_empty_result(self) -> CommandResult<()> {
         Err(CommandError{
             status:"error".to_string(),
-            error:self
+            error:self,
+            details: None
         })
     }
 }
@@ -24,5 +24,6 @@ interface InstanceCreateRequest {
 
 interface InstanceInfo{
     id:string,
-    name:string
+    name:string,
+    account:string | null, // the pinned account, the active user is used when null
 }
\ No newline at end of file
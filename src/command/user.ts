@@ -13,9 +13,20 @@ interface Caps{
     alias:string,
 }
 
+interface XboxProfile{
+    xuid:string,
+    gamertag:string,
+}
+
 interface Profile{
     id:string,
     name:string,
     skins:Array<Skin>,
     caps:Array<Caps>,
+    needs_relogin?:boolean,
+    demo?:boolean,
+    xbox?:XboxProfile,
+    nickname?:string,
+    order?:number,
+    last_used?:string,
 }
\ No newline at end of file
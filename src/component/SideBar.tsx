@@ -57,13 +57,22 @@ const modListSVG = (
 
 interface UUIDProps {
     id: string
+    className?: string
 }
 
 export function UserImage(props: UUIDProps) {
+    const [avatar, setAvatar] = useState<string | null>(null)
+
+    useEffect(() => {
+        invoke<string | null>("get_avatar", {id: props.id, kind: "head", size: 64})
+            .then(setAvatar)
+            .catch(console.error)
+    }, [props.id]);
+
     return (
         <img
-            className="object-cover w-9 h-9 p-0 rounded-md"
-            src={"https://crafatar.com/avatars/" + props.id}
+            className={props.className ?? "object-cover w-9 h-9 p-0 rounded-md"}
+            src={avatar ?? undefined}
             alt=""
         />
     )
@@ -152,6 +161,12 @@ export default function SideBar() {
         await listen<UUIDPayload>("change_user", (event) => {
             setUser(event.payload);
         });
+        await listen<NeedsReloginPayload>("needs_relogin", (event) => {
+            setUsers((users) => users.map((profile) => ({
+                ...profile,
+                needs_relogin: event.payload.uuids.includes(profile.id)
+            })));
+        });
     }
 
     useEffect(() => {
@@ -235,10 +250,15 @@ export default function SideBar() {
                                         <div className={user?.uuid == profile.id ? userNotSelect : userSelect}>
                                             <div className="flex-1"
                                                  onClick={() => invoke("set_current_user", {id: profile.id}).catch(console.error)}
-                                            ><img className="w-6 h-6 rounded-sm" src={"https://crafatar.com/avatars/" + profile.id}/>
+                                            ><UserImage className="w-6 h-6 rounded-sm" id={profile.id}/>
                                             </div>
                                             <div className="grow"
-                                                 onClick={() => invoke("set_current_user", {id: profile.id}).catch(console.error)}>{profile.name}</div>
+                                                 onClick={() => invoke("set_current_user", {id: profile.id}).catch(console.error)}>{profile.nickname ?? profile.name}
+                                                {profile.needs_relogin &&
+                                                    <span className="badge badge-warning badge-sm ml-2">login again</span>}
+                                                {profile.demo &&
+                                                    <span className="badge badge-ghost badge-sm ml-2">demo</span>}
+                                            </div>
 
                                             <div
                                                 className="text-right flex-none active:scale-90 duration-200">
@@ -1,5 +1,6 @@
 interface InstanceStatusChange{
     status: "Downloading" | "Checking" | "Preparing" | "Stopped" | "Failed" | "Running",
+    demo?: boolean, // running in demo mode, the account doesn't own the game
 }
 
 interface ProgressChange{
@@ -0,0 +1,12 @@
+type LoginProgress =
+    | { step: "DeviceCode", user_code: string, verification_uri: string, expire_in: string }
+    | { step: "WaitingForUser" }
+    | { step: "XboxLive" }
+    | { step: "XboxSecurity" }
+    | { step: "MinecraftToken" }
+    | { step: "Profile" }
+
+type LoginResult =
+    | { status: "Success", uuid: string }
+    | { status: "Failed", error: string, details?: { status: number | null, description: string | null, body: string } }
+    | { status: "Cancelled" }
@@ -1,3 +1,7 @@
 interface UUIDPayload {
     uuid: string;
-}
\ No newline at end of file
+}
+
+interface NeedsReloginPayload {
+    uuids: Array<string>;
+}
@@ -3,16 +3,23 @@ import {useEffect, useState} from "react";
 import {CenterView} from "../component/Compose.tsx";
 import {StepChild, StepParent} from "../component/Step.tsx";
 import {invoke } from "@tauri-apps/api/core"
+import {listen} from "@tauri-apps/api/event";
 
 interface Verify {
+    session: string,
     verification_uri: string,
     user_code: string,
-    expires_in: Date,
+    expire_in: string,
 }
 
 interface Status{
     status: "success" | "error",
-    description: string | null
+    description?: string
+}
+
+interface CommandError {
+    status: "error",
+    error: string,
 }
 
 
@@ -57,23 +64,60 @@ export function Auth() {
 
     useEffect(() => {
 
+        const descriptions = {
+            XboxLive: "getting the Xbox Live Auth Token......",
+            XboxSecurity: "Xbox Live Auth Success,Getting the XSTS token",
+            MinecraftToken: "getting the token for Minecraft",
+            Profile: "checking the profile...",
+        }
+
+        let session: string | null = null
+        let unlisten: Array<() => void> = []
+        let cancelled = false
+
         let works = async () => {
-            let verifiedJson = JSON.parse(await invoke("devicecode_init")) as Verify
-            setVerified(verifiedJson)
-            let exchangeJson = JSON.parse(await invoke("devicecode_exchange", {code: verifiedJson.user_code})) as Status
-            setDescription(exchangeJson)
-            let xboxLiveJson = JSON.parse(await invoke("xbox_live_auth", {token: exchangeJson.description})) as Status
-            setDescription(xboxLiveJson)
-            let xboxXSTSJson = JSON.parse(await invoke("xbox_xsts_auth", {token: xboxLiveJson.description})) as Status
-            setDescription(xboxXSTSJson)
-            let minecraftTokenJson = JSON.parse(await invoke("minecraft_token", {token: xboxXSTSJson.description})) as Status
-            setDescription(minecraftTokenJson)
-            let minecraftProfileJson = JSON.parse(await invoke("minecraft_profile", {token: minecraftTokenJson.description})) as Status
-            setDescription(minecraftProfileJson)
-            setAll(minecraftProfileJson.status === "success")
+            // resume the login which was waiting for user when app was closed
+            let verify = await invoke<Verify | null>("pending_login") ?? await invoke<Verify>("login")
+            session = verify.session
+            setVerified(verify)
+
+            unlisten.push(await listen<LoginProgress>("login_progress:" + verify.session, (event) => {
+                let progress = event.payload
+                if (progress.step !== "DeviceCode" && progress.step !== "WaitingForUser") {
+                    setDescription({status: "success", description: descriptions[progress.step]})
+                }
+            }))
+
+            unlisten.push(await listen<LoginResult>("login_finished:" + verify.session, (event) => {
+                let result = event.payload
+                if (result.status === "Failed") {
+                    setDescription({status: "error", description: result.details?.description ?? result.error})
+                    setAll(false)
+                } else if (result.status === "Success") {
+                    setAll(true)
+                }
+                // Cancelled is only sent when we leave this page.
+            }))
+
+            // the page is left before the login is started
+            if (cancelled) {
+                unlisten.forEach((x) => x())
+                await invoke("cancel_login", {session: verify.session})
+            }
         }
 
-        works().then(r => console.log(r)).catch(_ => setAll(false))
+        works().catch((e: CommandError) => {
+            setDescription({status: "error", description: e.error})
+            setAll(false)
+        })
+
+        return () => { // clean up
+            cancelled = true
+            if (session != null) {
+                invoke("cancel_login", {session}).catch(console.error)
+            }
+            unlisten.forEach((x) => x())
+        }
 
     }, [setVerified,setDescription, setAll])
 
@@ -80,7 +80,7 @@ function StatusCover(prop: InstanceIDProp) {
         <div className="flex flex-row">
             <span className="flex loading loading-spinner loading-xs p-2"></span>
         </div>
-        <div className="">running</div>
+        <div className="">{status.demo ? "running (demo)" : "running"}</div>
     </div>
 
     const stopeed = <div
@@ -2,6 +2,7 @@ import {useEffect, useState} from "react";
 import {CenterView} from "../component/Compose.tsx";
 import {Link, useNavigate, useParams} from "react-router-dom";
 import {invoke} from "@tauri-apps/api/core";
+import {UserImage} from "../component/SideBar.tsx";
 
 interface LoginCardProps {
     image?: string;
@@ -70,10 +71,9 @@ export function Login() {
                 {user.map((profile, _) => (
 
                     <div className={current?.uuid == profile.id ? select : noSelect}>
-                        <div className="basis-1/4"><img className="w-6 h-6 rounded-sm"
-                                                        src={"https://crafatar.com/avatars/" + profile.id}/>
+                        <div className="basis-1/4"><UserImage className="w-6 h-6 rounded-sm" id={profile.id}/>
                         </div>
-                        <div className="text-left basis-1/2" onClick={() => console.log("clicked")}>{profile.name}</div>
+                        <div className="text-left basis-1/2" onClick={() => console.log("clicked")}>{profile.nickname ?? profile.name}</div>
 
                         <div className="text-right basis-1/8 active:scale-90 duration-200">{setting}</div>
                         <div className="text-right basis-1/8 active:scale-90 duration-200">{logout}</div>